@@ -0,0 +1,85 @@
+//! Renders a scene file without a window and saves the GI output.
+//!
+//! `cargo run --example headless -- [scene] [output]` loads `scene` from the assets folder, lets
+//! the GI settle for a few frames, then writes it to `output`. Use a `.exr` output to keep the HDR
+//! values, anything else is saved as an LDR image.
+
+use std::time::Duration;
+
+use bevy::{
+    app::ScheduleRunnerPlugin, diagnostic::FrameCount, prelude::*, window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_radiance_cascades::prelude::*;
+
+/// Frames to render before saving, so the raymarch history has converged.
+const SETTLE_FRAMES: u32 = 120;
+
+#[derive(Resource)]
+struct Args {
+    scene: String,
+    output: String,
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let args = Args {
+        scene: args
+            .next()
+            .unwrap_or_else(|| "scenes/lights.scn.ron".to_string()),
+        output: args.next().unwrap_or_else(|| "gi.png".to_string()),
+    };
+
+    App::new()
+        .add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..Default::default()
+                })
+                .disable::<WinitPlugin>(),
+            // Without winit something else has to drive the frames
+            ScheduleRunnerPlugin::run_loop(Duration::ZERO),
+            CascadePlugin::default().with_offscreen_size(UVec2::new(800, 600)),
+        ))
+        .insert_resource(args)
+        .add_systems(Startup, setup)
+        .add_systems(Update, (save_when_settled, exit_when_saved))
+        .run();
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, args: Res<Args>) {
+    commands.spawn((
+        Camera2d,
+        Camera {
+            hdr: true,
+            ..Default::default()
+        },
+    ));
+    commands.spawn(DynamicSceneRoot(asset_server.load(&args.scene)));
+}
+
+fn save_when_settled(
+    frame_count: Res<FrameCount>,
+    args: Res<Args>,
+    mut save: EventWriter<SaveGiOutput>,
+) {
+    if frame_count.0 == SETTLE_FRAMES {
+        save.write(SaveGiOutput::new(&args.output));
+    }
+}
+
+fn exit_when_saved(mut saved: EventReader<GiOutputSaved>, mut exit: EventWriter<AppExit>) {
+    for saved in saved.read() {
+        match &saved.result {
+            Ok(()) => {
+                info!("Saved the GI output to {}", saved.path.display());
+                exit.write(AppExit::Success);
+            }
+            Err(_) => {
+                exit.write(AppExit::error());
+            }
+        }
+    }
+}
@@ -0,0 +1,783 @@
+//! Paint lights and walls with the mouse and watch them light each other up.
+//!
+//! Ctrl+S saves the canvas and Ctrl+O loads it back, from the file named in the side panel.
+//! Ctrl+Z takes back a stroke and Ctrl+Shift+Z puts it back. Right click switches to the next tool.
+//! The shape tools are dragged out, and painted when the mouse button is let go.
+//! Painting goes to the layer selected on the right.
+//! The arrow keys move the camera around the canvas, and - and = zoom out and in.
+//! Holding Tab shows the brute force raymarch in place of the cascades, to compare the two.
+//! The minimap at the bottom shows the whole canvas, lit by cheaper GI of its own.
+
+use std::f32::consts::{PI, TAU};
+
+use bevy::{
+    core_pipeline::tonemapping::Tonemapping,
+    diagnostic::DiagnosticsStore,
+    input::touch::{ForceTouch, Touch},
+    prelude::*,
+    render::{
+        camera::{ScalingMode, Viewport},
+        diagnostic::RenderDiagnosticsPlugin,
+        view::ColorGrading,
+    },
+};
+use bevy_egui::{EguiContextPass, EguiContexts, EguiPlugin, egui};
+use bevy_radiance_cascades::{CanvasLoaded, CanvasSaved, prelude::*};
+
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins,
+            // Lights up to a quarter of a screen out of view still light it
+            CascadePlugin::default().with_world_margin(256.0),
+            // Records the GPU time of the GI passes for the debug panel
+            RenderDiagnosticsPlugin,
+        ))
+        .add_plugins(EguiPlugin {
+            enable_multipass_for_primary_context: true,
+        })
+        .init_resource::<CanvasFile>()
+        .init_resource::<RadiusSource>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                update_settings,
+                canvas_shortcuts,
+                compare_with_raymarch,
+                move_camera,
+                place_minimap,
+                report_canvas_files,
+            ),
+        )
+        .add_systems(EguiContextPass, (side_panel_stroke_control, layer_panel))
+        .run();
+}
+
+/// Where the canvas is saved to and loaded from, and how the last attempt went.
+#[derive(Resource)]
+struct CanvasFile {
+    path: String,
+    status: String,
+}
+
+impl Default for CanvasFile {
+    fn default() -> Self {
+        Self {
+            path: "canvas.png".to_string(),
+            status: String::new(),
+        }
+    }
+}
+
+/// Marks the camera that shows the whole canvas in a corner of the window.
+#[derive(Component)]
+struct Minimap;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Main"),
+        Camera2d,
+        // The GI output is HDR, so the camera has to be too for the tonemapper to see the full range
+        Camera {
+            hdr: true,
+            ..Default::default()
+        },
+        Tonemapping::TonyMcMapface,
+        ColorGrading::default(),
+        PostProcessSettings::default(),
+        // Few rays per frame, the history accumulates them into a clean image
+        RaymarchSettings {
+            ray_count: 8,
+            max_steps: 128,
+            ..Default::default()
+        },
+        CascadeSettings::default(),
+    ));
+    // Renders after the main camera, which paints the canvas the minimap lights
+    commands.spawn((
+        Name::new("Minimap"),
+        Minimap,
+        Camera2d,
+        Camera {
+            hdr: true,
+            order: 1,
+            ..Default::default()
+        },
+        Tonemapping::TonyMcMapface,
+        ColorGrading::default(),
+        RaymarchSettings {
+            ray_count: 4,
+            ..Default::default()
+        },
+        // The minimap is small, so fewer cascades are enough
+        CascadeSettings {
+            cascade_count: 4,
+            ..Default::default()
+        },
+    ));
+}
+
+/// Keeps the minimap at the bottom of the window, fitting the whole canvas.
+fn place_minimap(
+    window: Query<&Window>,
+    domain: Option<Res<GiDomain>>,
+    mut minimap: Query<(&mut Camera, &mut Transform, &mut Projection), With<Minimap>>,
+) {
+    let (Ok(window), Ok((mut camera, mut transform, mut projection))) =
+        (window.single(), minimap.single_mut())
+    else {
+        return;
+    };
+    let window_size = window.physical_size();
+    let side = (window_size.y / 4).max(1);
+    camera.viewport = Some(Viewport {
+        physical_position: UVec2::new(
+            window_size.x.saturating_sub(side) / 2,
+            window_size.y.saturating_sub(side + 16),
+        ),
+        physical_size: UVec2::splat(side),
+        ..Default::default()
+    });
+    if let Some(domain) = domain {
+        transform.translation = domain.center.extend(transform.translation.z);
+        *projection = Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::AutoMin {
+                min_width: domain.size.x,
+                min_height: domain.size.y,
+            },
+            ..OrthographicProjection::default_2d()
+        });
+    }
+}
+
+/// What scales the brush radius along a stroke.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug)]
+enum RadiusSource {
+    #[default]
+    Constant,
+    /// The force of a pen or finger, on devices that report it.
+    Pressure,
+    /// Faster strokes paint thinner lines.
+    Speed,
+}
+
+/// How much a pen or finger pressed down, from 0.0 to 1.0.
+fn normalized_force(force: ForceTouch) -> f32 {
+    match force {
+        ForceTouch::Normalized(force) => force as f32,
+        ForceTouch::Calibrated {
+            force,
+            max_possible_force,
+            ..
+        } => (force / max_possible_force.max(f64::EPSILON)) as f32,
+    }
+}
+
+/// Turns the mouse and touch input of the frame into the brush stroke the canvas paints next.
+#[allow(clippy::too_many_arguments)]
+fn update_settings(
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    time: Res<Time>,
+    radius_source: Res<RadiusSource>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut touch_input: EventReader<TouchInput>,
+    window: Query<&Window>,
+    domain: Option<Res<GiDomain>>,
+    // Where last frame's stroke ended, kept in the world since the canvas may have moved under it
+    mut stroke_end: Local<Vec2>,
+    mut settings: Query<(
+        &mut PostProcessSettings,
+        &mut StrokePath,
+        &Camera,
+        &GlobalTransform,
+    )>,
+) {
+    let (Ok(window), Some(domain)) = (window.single(), domain) else {
+        return;
+    };
+    // A pen or finger on the screen paints like the left mouse button. A tap shorter than a frame has
+    // already ended, but it still paints a dot.
+    let touch = touches
+        .iter()
+        .next()
+        .or_else(|| touches.iter_just_pressed().next());
+    let started = mouse.just_pressed(MouseButton::Left) || touches.any_just_pressed();
+    let held = mouse.pressed(MouseButton::Left) || touches.iter().next().is_some();
+
+    // Every position the pointer passed through since the last frame, with the force of the touch
+    let mouse_samples: Vec<_> = cursor_moved
+        .read()
+        .map(|moved| (moved.position, None))
+        .collect();
+    let touch_samples: Vec<_> = touch_input
+        .read()
+        .filter(|input| touch.is_some_and(|touch| touch.id() == input.id))
+        .map(|input| (input.position, input.force))
+        .collect();
+    let samples = if touch.is_some() {
+        touch_samples
+    } else {
+        mouse_samples
+    };
+
+    for (mut canvas_setting, mut path, camera, camera_transform) in &mut settings {
+        if mouse.just_pressed(MouseButton::Right) {
+            let next = (canvas_setting.tool + 1) % CanvasTool::ALL.len() as u32;
+            canvas_setting.set_tool(CanvasTool::from_u32(next).unwrap_or_default());
+        }
+        // The brush paints in the canvas's own space, which follows the camera around the world
+        let to_world =
+            |position: Vec2| camera.viewport_to_world_2d(camera_transform, position).ok();
+        let to_canvas =
+            |position: Vec2| to_world(position).map(|world| domain.world_to_canvas(world));
+        let Some(cursor_world) = touch
+            .map(Touch::position)
+            .or(window.cursor_position())
+            .and_then(to_world)
+        else {
+            continue;
+        };
+        let cursor_pos = domain.world_to_canvas(cursor_world);
+        let samples: Vec<_> = samples
+            .iter()
+            .filter_map(|&(position, force)| Some((to_canvas(position)?, force)))
+            .collect();
+        canvas_setting.resolution = domain.size;
+        // Speed is measured between samples, which split the frame's time between them
+        let sample_time = time.delta_secs().max(f32::EPSILON) / samples.len().max(1) as f32;
+        let pressure = |force: Option<ForceTouch>, travelled: f32| match *radius_source {
+            RadiusSource::Constant => 1.0,
+            RadiusSource::Pressure => force
+                .or(touch.and_then(Touch::force))
+                .map_or(1.0, normalized_force),
+            RadiusSource::Speed => (1.0 - travelled / sample_time / 3000.0).clamp(0.2, 1.0),
+        };
+
+        // First frame of drawing
+        if started {
+            canvas_setting.drawing = 1;
+            canvas_setting.from = cursor_pos;
+            canvas_setting.to = cursor_pos;
+            *stroke_end = cursor_world;
+            canvas_setting.stroke_length = 0.0;
+            canvas_setting.continues_stroke = 0;
+            let pressure = match *radius_source {
+                RadiusSource::Speed => 1.0,
+                _ => pressure(samples.last().and_then(|(_, force)| *force), 0.0),
+            };
+            canvas_setting.from_pressure = pressure;
+            canvas_setting.to_pressure = pressure;
+            path.points.clear();
+        } else if held {
+            // Last frame's stroke has been painted, so its length counts towards the dab spacing
+            let mut previous = canvas_setting.from;
+            for point in path.points.iter().map(|point| point.position) {
+                canvas_setting.stroke_length += previous.distance(point);
+                previous = point;
+            }
+            canvas_setting.stroke_length += previous.distance(canvas_setting.to);
+
+            canvas_setting.drawing = 1;
+            canvas_setting.continues_stroke = 1;
+            canvas_setting.from = domain.world_to_canvas(*stroke_end);
+            canvas_setting.from_pressure = canvas_setting.to_pressure;
+
+            // The last sample is where the pointer is now, which is `to`
+            let mut previous = canvas_setting.from;
+            path.points.clear();
+            let intermediate = samples.len().saturating_sub(1);
+            for &(position, force) in &samples[..intermediate] {
+                path.points.push(StrokePoint {
+                    position,
+                    pressure: pressure(force, previous.distance(position)),
+                });
+                previous = position;
+            }
+            canvas_setting.to = cursor_pos;
+            *stroke_end = cursor_world;
+            canvas_setting.to_pressure = pressure(
+                samples.last().and_then(|(_, force)| *force),
+                previous.distance(cursor_pos),
+            );
+        } else {
+            canvas_setting.drawing = 0;
+            path.points.clear();
+        }
+    }
+}
+fn canvas_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    canvas_file: Res<CanvasFile>,
+    mut save: EventWriter<SaveCanvas>,
+    mut load: EventWriter<LoadCanvas>,
+    mut undo: EventWriter<UndoCanvas>,
+    mut redo: EventWriter<RedoCanvas>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyS) {
+        save.write(SaveCanvas::new(&canvas_file.path));
+    }
+    if keys.just_pressed(KeyCode::KeyO) {
+        load.write(LoadCanvas::new(&canvas_file.path));
+    }
+    if keys.just_pressed(KeyCode::KeyZ) {
+        if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            redo.write(RedoCanvas);
+        } else {
+            undo.write(UndoCanvas);
+        }
+    }
+}
+
+fn move_camera(
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<PostProcessSettings>>,
+) {
+    let Ok((mut transform, mut projection)) = camera.single_mut() else {
+        return;
+    };
+    let Projection::Orthographic(orthographic) = &mut *projection else {
+        return;
+    };
+    let mut direction = Vec2::ZERO;
+    for (key, step) in [
+        (KeyCode::ArrowLeft, Vec2::NEG_X),
+        (KeyCode::ArrowRight, Vec2::X),
+        (KeyCode::ArrowDown, Vec2::NEG_Y),
+        (KeyCode::ArrowUp, Vec2::Y),
+    ] {
+        if keys.pressed(key) {
+            direction += step;
+        }
+    }
+    // The same speed across the screen whatever the zoom
+    transform.translation +=
+        (direction * 500.0 * orthographic.scale * time.delta_secs()).extend(0.0);
+    if keys.pressed(KeyCode::Minus) {
+        orthographic.scale *= 1.0 + time.delta_secs();
+    }
+    if keys.pressed(KeyCode::Equal) {
+        orthographic.scale /= 1.0 + time.delta_secs();
+    }
+    orthographic.scale = orthographic.scale.clamp(0.25, 4.0);
+}
+
+/// Swaps the cascades for the brute force raymarch while Tab is held. The raymarch starts from the
+/// cascades' output as its history, so keep holding it for a moment while the raymarch converges.
+fn compare_with_raymarch(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut gi_mode: ResMut<GiMode>,
+    mut comparing: Local<bool>,
+) {
+    if keys.just_pressed(KeyCode::Tab) && *gi_mode == GiMode::Cascades {
+        *gi_mode = GiMode::Raymarch;
+        *comparing = true;
+    }
+    if keys.just_released(KeyCode::Tab) && *comparing {
+        *gi_mode = GiMode::Cascades;
+        *comparing = false;
+    }
+}
+
+fn report_canvas_files(
+    mut saved: EventReader<CanvasSaved>,
+    mut loaded: EventReader<CanvasLoaded>,
+    mut canvas_file: ResMut<CanvasFile>,
+) {
+    for saved in saved.read() {
+        canvas_file.status = match &saved.result {
+            Ok(()) => format!("Saved {}", saved.path.display()),
+            Err(error) => format!("Couldn't save: {error}"),
+        };
+    }
+    for loaded in loaded.read() {
+        canvas_file.status = match &loaded.result {
+            Ok(()) => format!("Loaded {}", loaded.path.display()),
+            Err(error) => format!("Couldn't load: {error}"),
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn side_panel_stroke_control(
+    mut contexts: EguiContexts,
+    mut canvas_file: ResMut<CanvasFile>,
+    mut save: EventWriter<SaveCanvas>,
+    mut load: EventWriter<LoadCanvas>,
+    history: Res<CanvasHistory>,
+    mut undo: EventWriter<UndoCanvas>,
+    mut redo: EventWriter<RedoCanvas>,
+    mut gi_mode: ResMut<GiMode>,
+    mut backend: ResMut<GiBackend>,
+    mut radius_source: ResMut<RadiusSource>,
+    mut debug_view: ResMut<GiDebugView>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut brushes: Query<(Entity, &mut PostProcessSettings)>,
+    mut gi_cameras: Query<(
+        Entity,
+        &Name,
+        &mut RaymarchSettings,
+        &mut CascadeSettings,
+        &mut SkyLight,
+        &mut Tonemapping,
+        &mut ColorGrading,
+    )>,
+    mut edited_camera: Local<Option<Entity>>,
+) {
+    let Ok((painter, mut canvas_settings)) = brushes.single_mut() else {
+        return;
+    };
+    // Every GI camera has settings of its own, the panel edits one at a time
+    let camera_names: Vec<_> = gi_cameras
+        .iter()
+        .map(|(camera, name, ..)| (camera, name.to_string()))
+        .collect();
+    let edited = edited_camera
+        .filter(|camera| gi_cameras.contains(*camera))
+        .unwrap_or(painter);
+    if let Ok((
+        _,
+        edited_name,
+        mut raymarch_settings,
+        mut cascade_settings,
+        mut sky_light,
+        mut tonemapping,
+        mut color_grading,
+    )) = gi_cameras.get_mut(edited)
+    {
+        let mut edited = edited;
+        let ctx = contexts.ctx_mut();
+        egui::SidePanel::left("left_panel").show(ctx, |ui| {
+            ui.label("Tool (Right Click):");
+            let mut tool = canvas_settings.tool();
+            ui.horizontal_wrapped(|ui| {
+                for candidate in CanvasTool::ALL {
+                    ui.radio_value(&mut tool, candidate, format!("{candidate:?}"));
+                }
+            });
+            canvas_settings.set_tool(tool);
+            if tool.is_shape() && tool != CanvasTool::Line {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut canvas_settings.shape_outline, 0, "Filled");
+                    ui.radio_value(&mut canvas_settings.shape_outline, 1, "Outlined");
+                });
+            }
+            if tool == CanvasTool::Polygon {
+                ui.add(
+                    egui::Slider::new(&mut canvas_settings.polygon_sides, 3..=12)
+                        .integer()
+                        .text("Sides"),
+                );
+            }
+
+            ui.separator();
+
+            ui.label("Stroke Color:");
+            ui.color_edit_button_rgb(canvas_settings.color.as_mut());
+
+            ui.separator();
+
+            ui.label("Stroke Emission:");
+            let mut emissive = canvas_settings.emission > 0.0;
+            if ui.checkbox(&mut emissive, "Emits Light").changed() {
+                // Without emission paint is a wall that only blocks light
+                canvas_settings.emission = if emissive { 1.0 } else { 0.0 };
+            }
+            ui.add(egui::Slider::new(&mut canvas_settings.emission, 0.0..=100.0).logarithmic(true));
+            ui.label("Density:");
+            // Below 1.0 the paint is translucent, like smoke or stained glass
+            ui.add(egui::Slider::new(&mut canvas_settings.density, 0.0..=1.0));
+
+            ui.separator();
+
+            ui.label("Stroke Radius:");
+
+            let mut radius = canvas_settings.radius_squared.sqrt();
+            let radius_slider_response =
+                ui.add(egui::Slider::new(&mut radius, 1.0..=50.0).text("Radius"));
+
+            if radius_slider_response.changed() {
+                canvas_settings.radius_squared = radius * radius;
+            }
+            ui.label("Radius From:");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut *radius_source, RadiusSource::Constant, "Constant");
+                ui.radio_value(&mut *radius_source, RadiusSource::Pressure, "Pen Pressure");
+                ui.radio_value(&mut *radius_source, RadiusSource::Speed, "Stroke Speed");
+            });
+            ui.label("Hardness:");
+            ui.add(egui::Slider::new(&mut canvas_settings.hardness, 0.0..=1.0));
+            ui.label("Opacity:");
+            ui.add(egui::Slider::new(&mut canvas_settings.opacity, 0.0..=1.0));
+            ui.label("Spacing:");
+            ui.add(egui::Slider::new(&mut canvas_settings.spacing, 0.0..=4.0));
+            ui.separator();
+
+            ui.label("GI Settings of Camera");
+            egui::ComboBox::from_id_salt("edited_camera")
+                .selected_text(edited_name.as_str())
+                .show_ui(ui, |ui| {
+                    for (camera, name) in &camera_names {
+                        ui.selectable_value(&mut edited, *camera, name.as_str());
+                    }
+                });
+            ui.separator();
+
+            ui.label("GI Mode");
+            ui.radio_value(&mut *gi_mode, GiMode::Cascades, "Radiance Cascades");
+            ui.radio_value(&mut *gi_mode, GiMode::Raymarch, "Brute Force Raymarch");
+            ui.separator();
+
+            match *gi_mode {
+                GiMode::Raymarch => {
+                    ui.label("Raymarch Steps");
+                    ui.add(egui::Slider::new(&mut raymarch_settings.max_steps, 1..=512).integer());
+                    ui.separator();
+                    ui.label("Amount of Rays");
+                    ui.add(egui::Slider::new(&mut raymarch_settings.ray_count, 1..=256).integer());
+                    ui.separator();
+                    ui.label("History Blend");
+                    ui.add(egui::Slider::new(
+                        &mut raymarch_settings.history_blend,
+                        0.0..=0.98,
+                    ));
+                    ui.separator();
+                    ui.label("Bounce Strength");
+                    ui.add(egui::Slider::new(
+                        &mut raymarch_settings.bounce_strength,
+                        0.0..=0.95,
+                    ));
+                }
+                GiMode::Cascades => {
+                    ui.label("Cascade Count");
+                    ui.add(
+                        egui::Slider::new(&mut cascade_settings.cascade_count, 1..=12).integer(),
+                    );
+                    ui.separator();
+                    ui.label("Base Interval");
+                    ui.add(egui::Slider::new(
+                        &mut cascade_settings.base_interval,
+                        0.5..=16.0,
+                    ));
+                    ui.separator();
+                    ui.label("Steps per Interval");
+                    ui.add(egui::Slider::new(&mut cascade_settings.max_steps, 1..=128).integer());
+                    ui.separator();
+                    ui.label("Bounce Strength");
+                    ui.add(egui::Slider::new(
+                        &mut cascade_settings.bounce_strength,
+                        0.0..=0.95,
+                    ));
+                    ui.separator();
+                    ui.label("Merge");
+                    egui::ComboBox::from_id_salt("cascade_merge")
+                        .selected_text(format!("{:?}", cascade_settings.merge))
+                        .show_ui(ui, |ui| {
+                            for merge in CascadeMerge::ALL {
+                                ui.selectable_value(
+                                    &mut cascade_settings.merge,
+                                    merge,
+                                    format!("{merge:?}"),
+                                );
+                            }
+                        });
+                    ui.label("Hold Tab to compare with the raymarch");
+                }
+            }
+            ui.separator();
+
+            ui.label("Sky");
+            let mut sky = *sky_light;
+            ui.horizontal(|ui| {
+                ui.label("Zenith");
+                ui.color_edit_button_rgb(sky.zenith.as_mut());
+                ui.label("Ground");
+                ui.color_edit_button_rgb(sky.ground.as_mut());
+            });
+            ui.horizontal(|ui| {
+                ui.label("Sun");
+                ui.color_edit_button_rgb(sky.sun_color.as_mut());
+            });
+            ui.label("Sun Angle");
+            ui.add(egui::Slider::new(&mut sky.sun_angle, 0.0..=TAU));
+            ui.label("Sun Size");
+            ui.add(egui::Slider::new(&mut sky.sun_angular_radius, 0.0..=PI));
+            ui.label("Sun Intensity");
+            ui.add(egui::Slider::new(&mut sky.sun_intensity, 0.0..=8.0));
+            if sky != *sky_light {
+                *sky_light = sky;
+            }
+            ui.separator();
+
+            ui.label("Tonemapping");
+            egui::ComboBox::from_id_salt("tonemapping")
+                .selected_text(format!("{:?}", *tonemapping))
+                .show_ui(ui, |ui| {
+                    for method in [
+                        Tonemapping::None,
+                        Tonemapping::Reinhard,
+                        Tonemapping::ReinhardLuminance,
+                        Tonemapping::AcesFitted,
+                        Tonemapping::AgX,
+                        Tonemapping::SomewhatBoringDisplayTransform,
+                        Tonemapping::TonyMcMapface,
+                        Tonemapping::BlenderFilmic,
+                    ] {
+                        ui.selectable_value(&mut *tonemapping, method, format!("{method:?}"));
+                    }
+                });
+            ui.label("Exposure (EV)");
+            ui.add(egui::Slider::new(
+                &mut color_grading.global.exposure,
+                -8.0..=8.0,
+            ));
+            ui.separator();
+
+            ui.label("Debug View");
+            ui.radio_value(&mut *debug_view, GiDebugView::None, "Lit Canvas");
+            ui.radio_value(
+                &mut *debug_view,
+                GiDebugView::DistanceField,
+                "Distance Field",
+            );
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(history.can_undo(), egui::Button::new("Undo (Ctrl+Z)"))
+                    .clicked()
+                {
+                    undo.write(UndoCanvas);
+                }
+                if ui
+                    .add_enabled(history.can_redo(), egui::Button::new("Redo (Ctrl+Shift+Z)"))
+                    .clicked()
+                {
+                    redo.write(RedoCanvas);
+                }
+            });
+            ui.label(format!(
+                "Undo history: {:.1} / {:.0} MiB",
+                history.memory_used() as f32 / (1024.0 * 1024.0),
+                history.budget() as f32 / (1024.0 * 1024.0),
+            ));
+            ui.separator();
+
+            ui.label("Canvas File");
+            ui.text_edit_singleline(&mut canvas_file.path);
+            ui.horizontal(|ui| {
+                if ui.button("Save (Ctrl+S)").clicked() {
+                    save.write(SaveCanvas::new(&canvas_file.path));
+                }
+                if ui.button("Load (Ctrl+O)").clicked() {
+                    load.write(LoadCanvas::new(&canvas_file.path));
+                }
+            });
+            if !canvas_file.status.is_empty() {
+                ui.label(&canvas_file.status);
+            }
+            ui.separator();
+
+            ui.label("GI Backend");
+            ui.radio_value(&mut *backend, GiBackend::Fragment, "Fragment");
+            ui.radio_value(&mut *backend, GiBackend::Compute, "Compute");
+            // Each backend keeps its last measurement, so switching between them compares the two
+            for candidate in [GiBackend::Fragment, GiBackend::Compute] {
+                let gpu_time = diagnostics
+                    .get(&candidate.diagnostic_path(*gi_mode))
+                    .and_then(|diagnostic| diagnostic.smoothed());
+                ui.label(match gpu_time {
+                    Some(ms) => format!("{candidate:?}: {ms:.3} ms"),
+                    None => format!("{candidate:?}: no GPU timings"),
+                });
+            }
+        });
+        *edited_camera = Some(edited);
+    }
+}
+
+fn layer_panel(
+    mut contexts: EguiContexts,
+    layers: Option<ResMut<CanvasLayers>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // The layers are created along with the canvas, once the camera is there
+    let Some(mut layers) = layers else {
+        return;
+    };
+    let ctx = contexts.ctx_mut();
+    egui::SidePanel::right("layer_panel").show(ctx, |ui| {
+        ui.label("Layers");
+        if ui.button("Add Layer").clicked() {
+            let name = format!("Layer {}", layers.len() + 1);
+            layers.add(name, &mut images);
+        }
+        ui.separator();
+
+        let active = layers.active_index();
+        let top = layers.len() - 1;
+        let mut selected = None;
+        let mut moved = None;
+        let mut removed = None;
+        // The top of the stack is listed first, like in an image editor
+        for (index, layer) in layers.iter_mut().enumerate().rev() {
+            ui.horizontal(|ui| {
+                if ui.radio(index == active, "").clicked() {
+                    selected = Some(index);
+                }
+                ui.checkbox(&mut layer.visible, "");
+                ui.text_edit_singleline(&mut layer.name);
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(("blend", layer.id()))
+                    .selected_text(format!("{:?}", layer.blend))
+                    .show_ui(ui, |ui| {
+                        for blend in LayerBlend::ALL {
+                            ui.selectable_value(&mut layer.blend, blend, format!("{blend:?}"));
+                        }
+                    });
+                egui::ComboBox::from_id_salt(("role", layer.id()))
+                    .selected_text(format!("{:?}", layer.role))
+                    .show_ui(ui, |ui| {
+                        for role in LayerRole::ALL {
+                            ui.selectable_value(&mut layer.role, role, format!("{role:?}"));
+                        }
+                    });
+                if ui
+                    .add_enabled(index < top, egui::Button::new("Up"))
+                    .clicked()
+                {
+                    moved = Some((index, index + 1));
+                }
+                if ui
+                    .add_enabled(index > 0, egui::Button::new("Down"))
+                    .clicked()
+                {
+                    moved = Some((index, index - 1));
+                }
+                if ui
+                    .add_enabled(top > 0, egui::Button::new("Remove"))
+                    .clicked()
+                {
+                    removed = Some(index);
+                }
+            });
+            ui.separator();
+        }
+
+        if let Some(index) = selected {
+            layers.set_active(index);
+        }
+        if let Some((from, to)) = moved {
+            layers.move_layer(from, to);
+        }
+        if let Some(index) = removed {
+            layers.remove(index);
+        }
+    });
+}
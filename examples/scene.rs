@@ -0,0 +1,115 @@
+//! Lights and walls made of entities instead of paint. The lights move, and the GI follows them.
+//!
+//! Sprites and meshes with a `GiContributor` take part as well.
+
+use std::f32::consts::TAU;
+
+use bevy::{core_pipeline::tonemapping::Tonemapping, prelude::*};
+use bevy_radiance_cascades::prelude::*;
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, CascadePlugin::default()))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (orbit, spin))
+        .run();
+}
+
+/// Circles around the origin at `radius`, starting at `phase`.
+#[derive(Component)]
+struct Orbit {
+    radius: f32,
+    phase: f32,
+}
+
+#[derive(Component)]
+struct Spin;
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn((
+        Camera2d,
+        Camera {
+            hdr: true,
+            ..Default::default()
+        },
+        Tonemapping::TonyMcMapface,
+    ));
+
+    let wall = Color::srgb(0.8, 0.8, 0.8);
+    commands.spawn((
+        RadianceOccluder::new(
+            RadianceShape::Box {
+                half_size: Vec2::new(120.0, 15.0),
+            },
+            wall,
+        ),
+        Transform::from_xyz(0.0, 120.0, 0.0),
+    ));
+    commands.spawn((
+        RadianceOccluder::new(
+            RadianceShape::Segment {
+                start: Vec2::new(-250.0, -200.0),
+                end: Vec2::new(-100.0, -80.0),
+                thickness: 8.0,
+            },
+            wall,
+        ),
+        Transform::default(),
+    ));
+    commands.spawn((
+        RadianceOccluder::new(
+            RadianceShape::Capsule {
+                half_length: 80.0,
+                radius: 12.0,
+            },
+            Color::srgb(0.9, 0.3, 0.3),
+        ),
+        Transform::from_xyz(0.0, -60.0, 0.0),
+        Spin,
+    ));
+
+    // A sprite wall, lit like any painted one
+    commands.spawn((
+        Sprite::from_color(Color::srgb(0.3, 0.9, 0.4), Vec2::new(30.0, 160.0)),
+        Transform::from_xyz(200.0, 0.0, 0.0),
+        GiContributor::occluder(),
+    ));
+    // A glowing mesh, rendered normally and as a light
+    let glow = Color::srgb(0.9, 0.4, 1.0);
+    commands.spawn((
+        Mesh2d(meshes.add(RegularPolygon::new(30.0, 6))),
+        MeshMaterial2d(materials.add(glow)),
+        Transform::from_xyz(-200.0, 100.0, 0.0),
+        GiContributor::emissive(glow, 3.0),
+    ));
+
+    for (i, color) in [Color::srgb(1.0, 0.7, 0.3), Color::srgb(0.3, 0.6, 1.0)]
+        .into_iter()
+        .enumerate()
+    {
+        commands.spawn((
+            RadianceEmitter::new(RadianceShape::Circle { radius: 18.0 }, color, 4.0),
+            Orbit {
+                radius: 220.0,
+                phase: i as f32 * TAU / 2.0,
+            },
+        ));
+    }
+}
+
+fn orbit(time: Res<Time>, mut lights: Query<(&Orbit, &mut Transform)>) {
+    for (orbit, mut transform) in &mut lights {
+        let angle = orbit.phase + time.elapsed_secs() * 0.5;
+        transform.translation = (Vec2::from_angle(angle) * orbit.radius).extend(0.0);
+    }
+}
+
+fn spin(time: Res<Time>, mut walls: Query<&mut Transform, With<Spin>>) {
+    for mut transform in &mut walls {
+        transform.rotate_z(time.delta_secs());
+    }
+}
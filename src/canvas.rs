@@ -0,0 +1,455 @@
+use bevy::{
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        Extract,
+        extract_component::{ComponentUniforms, DynamicUniformIndex, ExtractComponent},
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{
+            binding_types::{storage_buffer_read_only, texture_2d, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::GpuImage,
+    },
+};
+
+use crate::{CANVAS_SHADER_ASSET_PATH, GiTextureFormats, layers::CanvasLayers};
+
+/// Paints strokes into the active layer of the canvas.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct CanvasPassLabel;
+
+#[derive(Default)]
+pub(crate) struct CanvasNode;
+
+/// The brush used to paint into the canvas, and the stroke to paint this frame, which runs from
+/// `from` through every point of the [`StrokePath`] to `to`.
+///
+/// The camera with these settings paints the canvas, which every GI camera lights. Only the camera
+/// [`CascadePlugin`](crate::CascadePlugin) attaches to is given them, and there should only be one.
+#[derive(Component, Clone, Copy, ExtractComponent, ShaderType, AsBindGroup)]
+pub struct PostProcessSettings {
+    /// The size of the space `from` and `to` are given in, usually the window's logical size.
+    pub resolution: Vec2,
+    pub radius_squared: f32,
+    /// Nonzero while the segment from `from` to `to` should be painted.
+    pub drawing: u32,
+    pub from: Vec2,
+    pub to: Vec2,
+    /// The albedo of painted surfaces.
+    pub color: Vec3,
+    /// How strongly painted surfaces emit their colour. Zero paints walls that only block light.
+    pub emission: f32,
+    /// What the stroke does, a [`CanvasTool`] as a `u32` so it can go into the uniform.
+    pub tool: u32,
+    /// How much of the radius is painted at full strength, 1.0 for a hard edge. Past that the brush
+    /// fades out towards the edge, and the light the paint gives off fades with it.
+    pub hardness: f32,
+    /// How much paint a single pass of the brush leaves, from 0.0 to 1.0. Paint that covers half a
+    /// pixel or less lets light through like translucent paint, glowing by as much as it covers.
+    pub opacity: f32,
+    /// The distance between dabs of the brush, as a fraction of its radius. 0.0 paints a continuous
+    /// stroke, where opacity can build up a little where the segments meet.
+    pub spacing: f32,
+    /// How far along the stroke `from` is, so the dabs stay evenly spaced from segment to segment.
+    pub stroke_length: f32,
+    /// Nonzero for every segment of a stroke but the first, which then leave out the end the segment
+    /// before already painted.
+    pub continues_stroke: u32,
+    /// Scales the radius at `from`, such as by pen pressure.
+    pub from_pressure: f32,
+    /// Scales the radius at `to`. The radius is interpolated in between.
+    pub to_pressure: f32,
+    /// Where the drag of a shape tool started. Kept up to date by the plugin.
+    pub shape_start: Vec2,
+    /// Nonzero on the frame a shape tool's drag ends, which is when the shape from `shape_start` to
+    /// `to` is painted. Kept up to date by the plugin.
+    pub commit_shape: u32,
+    /// Nonzero to paint only the edge of rectangles, ellipses and polygons, as wide as the brush.
+    pub shape_outline: u32,
+    /// How many sides [`CanvasTool::Polygon`] draws.
+    pub polygon_sides: u32,
+    /// 1.0 paints solid surfaces that block light. Below that the paint is a translucent medium, like
+    /// smoke or stained glass, that lets through `color` and gets more opaque with every pixel of it
+    /// the light crosses. This is how much of the light it stops per pixel, without the tint.
+    pub density: f32,
+    /// How many canvas pixels the canvas moved by since last frame, when the GI follows the camera.
+    /// The paint moves the other way to stay where it was in the world. Kept up to date by the plugin.
+    pub scroll: IVec2,
+}
+
+/// Where the stroke went between `from` and `to` of [`PostProcessSettings`], for pointers that moved
+/// more than once since the last frame. Without it the stroke is a straight line.
+#[derive(Component, Clone, Debug, Default)]
+pub struct StrokePath {
+    pub points: Vec<StrokePoint>,
+}
+
+/// A point the stroke passed through.
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct StrokePoint {
+    /// In the same space as `from` and `to`.
+    pub position: Vec2,
+    /// Scales the radius here, like `from_pressure` and `to_pressure`.
+    pub pressure: f32,
+}
+
+#[derive(Default, ShaderType)]
+struct GpuStrokePath {
+    count: u32,
+    #[size(runtime)]
+    points: Vec<StrokePoint>,
+}
+
+/// The points of this frame's [`StrokePath`], as the canvas pass reads them.
+#[derive(Resource, Default)]
+pub(crate) struct StrokeBuffer {
+    extracted: Vec<StrokePoint>,
+    buffer: StorageBuffer<GpuStrokePath>,
+}
+
+pub(crate) fn extract_stroke_path(
+    mut stroke: ResMut<StrokeBuffer>,
+    paths: Extract<Query<&StrokePath, With<PostProcessSettings>>>,
+) {
+    stroke.extracted.clear();
+    if let Ok(path) = paths.single() {
+        stroke.extracted.extend_from_slice(&path.points);
+    }
+}
+
+pub(crate) fn prepare_stroke_path(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut stroke: ResMut<StrokeBuffer>,
+) {
+    let mut points = stroke.extracted.clone();
+    let count = points.len() as u32;
+    // A storage binding needs at least one element, even when the stroke is a straight line.
+    if points.is_empty() {
+        points.push(StrokePoint::default());
+    }
+    stroke.buffer.set(GpuStrokePath { count, points });
+    stroke.buffer.write_buffer(&render_device, &render_queue);
+}
+
+impl PostProcessSettings {
+    pub fn tool(&self) -> CanvasTool {
+        CanvasTool::from_u32(self.tool).unwrap_or_default()
+    }
+
+    pub fn set_tool(&mut self, tool: CanvasTool) {
+        self.tool = tool as u32;
+    }
+
+    /// The albedo alpha the brush paints, which is how the GI tells solid paint from translucent
+    /// paint. Matches `paint_alpha` in `canvas.wgsl`.
+    pub fn paint_alpha(&self) -> f32 {
+        if self.density >= 1.0 {
+            1.0
+        } else {
+            self.density.max(0.0) * MEDIUM_MAX_ALPHA
+        }
+    }
+}
+
+/// Albedo alphas above this are solid, and the ones at or below it are translucent paint.
+pub(crate) const MEDIUM_MAX_ALPHA: f32 = 0.5;
+
+/// What a stroke does to the canvas. The values match the `TOOL_` constants in `canvas.wgsl`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CanvasTool {
+    /// Paints `color` along the stroke.
+    #[default]
+    Brush = 0,
+    /// Removes paint along the stroke, leaving empty space.
+    Eraser = 1,
+    /// Removes all of the paint on the canvas.
+    Clear = 2,
+    /// Paints `color` over the region of identical paint, or empty space, where the stroke starts.
+    /// This happens on the CPU, so it lands a few frames after the click, and the layer can't be
+    /// painted on until it has.
+    Fill = 3,
+    /// Paints the rectangle with opposite corners at the start and end of the drag.
+    Rectangle = 4,
+    /// Paints the ellipse that fits inside the rectangle the drag spans.
+    Ellipse = 5,
+    /// Paints a regular polygon around the start of the drag, with a corner at its end.
+    Polygon = 6,
+    /// Paints a straight line from the start of the drag to its end, as wide as the brush.
+    Line = 7,
+}
+
+impl CanvasTool {
+    pub const ALL: [Self; 8] = [
+        Self::Brush,
+        Self::Eraser,
+        Self::Clear,
+        Self::Fill,
+        Self::Rectangle,
+        Self::Ellipse,
+        Self::Polygon,
+        Self::Line,
+    ];
+
+    pub fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|tool| *tool as u32 == value)
+    }
+
+    /// Whether the tool is dragged out into a shape, painted once the drag ends, rather than
+    /// painting along the stroke.
+    pub fn is_shape(self) -> bool {
+        matches!(
+            self,
+            Self::Rectangle | Self::Ellipse | Self::Polygon | Self::Line
+        )
+    }
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            resolution: Vec2::ZERO,
+            radius_squared: 100.0,
+            drawing: 0,
+            from: Vec2::ZERO,
+            to: Vec2::ZERO,
+            color: Vec3::new(0.0, 0.0, 1.0),
+            emission: 1.0,
+            tool: CanvasTool::Brush as u32,
+            hardness: 1.0,
+            opacity: 1.0,
+            spacing: 0.0,
+            stroke_length: 0.0,
+            continues_stroke: 0,
+            from_pressure: 1.0,
+            to_pressure: 1.0,
+            shape_start: Vec2::ZERO,
+            commit_shape: 0,
+            shape_outline: 0,
+            polygon_sides: 6,
+            density: 1.0,
+            scroll: IVec2::ZERO,
+        }
+    }
+}
+
+impl ViewNode for CanvasNode {
+    type ViewQuery = (
+        &'static PostProcessSettings,
+        &'static DynamicUniformIndex<PostProcessSettings>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (settings, settings_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let post_process_pipeline = world.resource::<CanvasPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(post_process_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let settings_uniforms = world.resource::<ComponentUniforms<PostProcessSettings>>();
+        let (Some(settings_binding), Some(stroke_binding)) = (
+            settings_uniforms.uniforms().binding(),
+            world.resource::<StrokeBuffer>().buffer.binding(),
+        ) else {
+            return Ok(());
+        };
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let layers = world.resource::<CanvasLayers>();
+        let scroll = settings.scroll;
+
+        // Nothing is painted into the other layers, so they're carried over to this frame's side as they are
+        for (index, layer) in layers.iter().enumerate() {
+            if index == layers.active_index() {
+                continue;
+            }
+            let (source, target) = (layers.source(layer), layers.target(layer));
+            for (from, to) in [
+                (&source.emission, &target.emission),
+                (&source.albedo, &target.albedo),
+            ] {
+                let (Some(from), Some(to)) = (gpu_images.get(from), gpu_images.get(to)) else {
+                    continue;
+                };
+                // Like the canvas pass, a resized or moved side keeps whatever of the layer still fits
+                let source_origin = scroll.max(IVec2::ZERO);
+                let target_origin = (-scroll).max(IVec2::ZERO);
+                let source_size =
+                    UVec2::new(from.texture.width(), from.texture.height()).as_ivec2();
+                let target_size = UVec2::new(to.texture.width(), to.texture.height()).as_ivec2();
+                let size = (source_size - source_origin).min(target_size - target_origin);
+                if scroll != IVec2::ZERO {
+                    // The part of the world that just came into the canvas has no paint yet
+                    render_context
+                        .command_encoder()
+                        .begin_render_pass(&RenderPassDescriptor {
+                            label: Some("clear_moved_layer_pass"),
+                            color_attachments: &[Some(RenderPassColorAttachment {
+                                view: &to.texture_view,
+                                resolve_target: None,
+                                ops: Operations {
+                                    load: LoadOp::Clear(LinearRgba::NONE.into()),
+                                    store: StoreOp::Store,
+                                },
+                            })],
+                            depth_stencil_attachment: None,
+                            timestamp_writes: None,
+                            occlusion_query_set: None,
+                        });
+                }
+                if size.min_element() <= 0 {
+                    continue;
+                }
+                let origin = |origin: IVec2| Origin3d {
+                    x: origin.x as u32,
+                    y: origin.y as u32,
+                    z: 0,
+                };
+                render_context.command_encoder().copy_texture_to_texture(
+                    TexelCopyTextureInfo {
+                        origin: origin(source_origin),
+                        ..from.texture.as_image_copy()
+                    },
+                    TexelCopyTextureInfo {
+                        origin: origin(target_origin),
+                        ..to.texture.as_image_copy()
+                    },
+                    Extent3d {
+                        width: size.x as u32,
+                        height: size.y as u32,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let source = layers.source(layers.active());
+        let target = layers.target(layers.active());
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "post_process_bind_group",
+            &post_process_pipeline.layout,
+            &BindGroupEntries::sequential((
+                &gpu_images.get(&source.emission).unwrap().texture_view,
+                &gpu_images.get(&source.albedo).unwrap().texture_view,
+                settings_binding.clone(),
+                stroke_binding,
+            )),
+        );
+
+        let color_attachment = move |image: &Handle<Image>| {
+            Some(RenderPassColorAttachment {
+                view: &gpu_images.get(image).unwrap().texture_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })
+        };
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("post_process_pass"),
+            // Every channel of the G-buffer is written at once, in the same order as the shader's outputs
+            color_attachments: &[
+                color_attachment(&target.emission),
+                color_attachment(&target.albedo),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[settings_index.index()]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct CanvasPipeline {
+    layout: BindGroupLayout,
+    pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for CanvasPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let formats = *world.resource::<GiTextureFormats>();
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "post_process_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                // The layout entries will only be visible in the fragment stage
+                ShaderStages::FRAGMENT,
+                (
+                    // The emission channel of the canvas
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // The albedo and occlusion channels of the canvas
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // The settings uniform that will control the effect
+                    uniform_buffer::<PostProcessSettings>(true),
+                    // The points the stroke passed through between `from` and `to`
+                    storage_buffer_read_only::<GpuStrokePath>(false),
+                ),
+            ),
+        );
+
+        // Get the shader handle
+        let shader = world.load_asset(CANVAS_SHADER_ASSET_PATH);
+
+        let pipeline_id = world
+            .resource_mut::<PipelineCache>()
+            // This will add the pipeline to the cache and queue its creation
+            .queue_render_pipeline(RenderPipelineDescriptor {
+                label: Some("post_process_pipeline".into()),
+                layout: vec![layout.clone()],
+                // This will setup a fullscreen triangle for the vertex state
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader,
+                    shader_defs: vec![],
+                    entry_point: "fragment".into(),
+                    // One target per G-buffer channel
+                    targets: vec![
+                        Some(ColorTargetState {
+                            format: formats.emission,
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        }),
+                        Some(ColorTargetState {
+                            format: TextureFormat::Rgba8Unorm,
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        }),
+                    ],
+                }),
+                // All of the following properties are not important for this effect so just use the default values.
+                // This struct doesn't have the Default trait implemented because not all fields can have a default value.
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                push_constant_ranges: vec![],
+                zero_initialize_workgroup_memory: false,
+            });
+
+        Self {
+            layout,
+            pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,855 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{
+        gpu_readback::{Readback, ReadbackComplete},
+        render_resource::TextureFormat,
+    },
+};
+use half::f16;
+use image::{DynamicImage, Rgba32FImage, imageops::FilterType};
+
+use crate::{
+    capture::{texels_to_f32, unpad_texels},
+    fill::{PendingFills, flood_fill},
+    history::{CanvasHistory, LayerSnapshot},
+    layers::{CanvasLayer, CanvasLayers, LayerBlend, LayerId, LayerRole},
+};
+
+/// Saves every layer of the canvas to `path`.
+///
+/// The PNG at `path` holds the albedo of the bottom layer, with the occluder mask in alpha, and each
+/// layer above it goes to [`canvas_layer_path`]. Emission is HDR, so each layer's is written next to
+/// its albedo as OpenEXR, at [`canvas_emission_path`]. The name, visibility, blend and role of every
+/// layer are listed in [`canvas_layers_path`].
+#[derive(Event, Clone, Debug)]
+pub struct SaveCanvas {
+    pub path: PathBuf,
+}
+
+impl SaveCanvas {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+/// Replaces the canvas with the one saved at `path` by [`SaveCanvas`], layers and all, stretched to
+/// the size of the canvas.
+///
+/// Any other image, with no [`canvas_layers_path`] next to it, replaces the active layer alone. It
+/// gets its emission from [`canvas_emission_path`] if there is one there, and otherwise loads as
+/// albedo alone, so nothing on it glows.
+#[derive(Event, Clone, Debug)]
+pub struct LoadCanvas {
+    pub path: PathBuf,
+}
+
+impl LoadCanvas {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+/// Sent once the files of a [`SaveCanvas`] have been written, or couldn't be.
+#[derive(Event, Clone, Debug)]
+pub struct CanvasSaved {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+}
+
+/// Sent once a [`LoadCanvas`] has replaced the canvas, or couldn't.
+#[derive(Event, Clone, Debug)]
+pub struct CanvasLoaded {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+}
+
+/// Where the emission of the canvas saved at `path` is kept, `canvas.png` has `canvas.emission.exr`.
+/// The emission of a layer above the bottom one is next to its [`canvas_layer_path`] the same way.
+pub fn canvas_emission_path(path: &Path) -> PathBuf {
+    path.with_extension("emission.exr")
+}
+
+/// Where the albedo of the layer at `index` of the canvas saved at `path` is kept, counting from the
+/// bottom. The bottom layer is at `path` itself, and `canvas.png` has the next one at
+/// `canvas.layer1.png`.
+pub fn canvas_layer_path(path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    let extension = path
+        .extension()
+        .map_or("png".into(), |extension| extension.to_string_lossy());
+    path.with_extension(format!("layer{index}.{extension}"))
+}
+
+/// Where the layers of the canvas saved at `path` are listed, `canvas.png` has `canvas.layers`.
+///
+/// Each line is a layer from the bottom up, holding whether it's visible, its [`LayerBlend`], its
+/// [`LayerRole`] and its name, separated by tabs.
+pub fn canvas_layers_path(path: &Path) -> PathBuf {
+    path.with_extension("layers")
+}
+
+/// Everything about a layer a canvas file keeps, other than its paint.
+#[derive(Clone, Debug, PartialEq)]
+struct LayerInfo {
+    name: String,
+    visible: bool,
+    blend: LayerBlend,
+    role: LayerRole,
+}
+
+impl LayerInfo {
+    fn of(layer: &CanvasLayer) -> Self {
+        Self {
+            name: layer.name.clone(),
+            visible: layer.visible,
+            blend: layer.blend,
+            role: layer.role,
+        }
+    }
+
+    fn apply(&self, layer: &mut CanvasLayer) {
+        layer.name.clone_from(&self.name);
+        layer.visible = self.visible;
+        layer.blend = self.blend;
+        layer.role = self.role;
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{:?}\t{:?}\t{}",
+            self.visible, self.blend, self.role, self.name
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let visible = fields.next()?.parse().ok()?;
+        let blend = fields.next()?;
+        let blend = LayerBlend::ALL
+            .into_iter()
+            .find(|candidate| format!("{candidate:?}") == blend)?;
+        let role = fields.next()?;
+        let role = LayerRole::ALL
+            .into_iter()
+            .find(|candidate| format!("{candidate:?}") == role)?;
+        Some(Self {
+            name: fields.next()?.to_string(),
+            visible,
+            blend,
+            role,
+        })
+    }
+}
+
+/// One layer of a canvas file.
+struct CanvasFileLayer {
+    /// `None` for an image that wasn't saved with its layers.
+    info: Option<LayerInfo>,
+    emission: Option<Rgba32FImage>,
+    albedo: Rgba32FImage,
+}
+
+/// A copy of one channel of the canvas, as the GPU stores it.
+#[derive(Clone)]
+pub(crate) struct CanvasTexels {
+    pub size: UVec2,
+    pub format: TextureFormat,
+    pub data: Vec<u8>,
+}
+
+impl CanvasTexels {
+    fn to_image(&self) -> Result<Rgba32FImage, String> {
+        Rgba32FImage::from_raw(
+            self.size.x,
+            self.size.y,
+            texels_to_f32(&self.data, self.format)?,
+        )
+        .ok_or_else(|| "the canvas doesn't match its size".to_string())
+    }
+}
+
+/// What a finished canvas readback is for.
+pub(crate) enum CanvasReadbackPurpose {
+    /// The layer at `index` of the [`PendingCanvasSave`] on `save`.
+    SaveLayer { save: Entity, index: usize },
+    /// The layer at `index` of the [`PendingCanvasSnapshot`] on `snapshot`.
+    SnapshotLayer { snapshot: Entity, index: usize },
+    /// Paint to flood fill `layer` with from `seed`, in canvas pixels.
+    Fill {
+        layer: LayerId,
+        seed: UVec2,
+        emission: Vec4,
+        albedo: Vec4,
+    },
+}
+
+/// A canvas readback waiting on both of its channels.
+#[derive(Component)]
+struct PendingCanvasReadback {
+    purpose: CanvasReadbackPurpose,
+    emission: Option<Result<CanvasTexels, String>>,
+    albedo: Option<Result<CanvasTexels, String>>,
+}
+
+#[derive(Clone, Copy)]
+enum CanvasChannel {
+    Emission,
+    Albedo,
+}
+
+/// A [`SaveCanvas`] waiting on the readbacks of its layers.
+#[derive(Component)]
+struct PendingCanvasSave {
+    path: PathBuf,
+    layers: Vec<(LayerInfo, Option<Result<CanvasFileLayer, String>>)>,
+}
+
+/// A state of some layers to return to with [`UndoCanvas`](crate::UndoCanvas), waiting on their
+/// readbacks.
+#[derive(Component)]
+struct PendingCanvasSnapshot {
+    layers: Vec<(LayerId, Option<Result<LayerSnapshot, String>>)>,
+}
+
+/// The readback of one channel of a [`PendingCanvasReadback`].
+#[derive(Component)]
+struct CanvasChannelReadback {
+    pending: Entity,
+    channel: CanvasChannel,
+    size: UVec2,
+    format: TextureFormat,
+}
+
+/// Reads back the side of `layer` written this frame, which has everything painted so far. Returns
+/// false if the layer isn't ready to be read.
+pub(crate) fn spawn_canvas_readback(
+    commands: &mut Commands,
+    layers: &CanvasLayers,
+    layer: &CanvasLayer,
+    images: &Assets<Image>,
+    purpose: CanvasReadbackPurpose,
+) -> bool {
+    let target = layers.target(layer);
+    let (Some(emission), Some(albedo)) = (images.get(&target.emission), images.get(&target.albedo))
+    else {
+        return false;
+    };
+
+    let pending = commands
+        .spawn(PendingCanvasReadback {
+            purpose,
+            emission: None,
+            albedo: None,
+        })
+        .id();
+    for (channel, handle, image) in [
+        (CanvasChannel::Emission, &target.emission, emission),
+        (CanvasChannel::Albedo, &target.albedo, albedo),
+    ] {
+        commands
+            .spawn((
+                Readback::texture(handle.clone()),
+                CanvasChannelReadback {
+                    pending,
+                    channel,
+                    size: image.size(),
+                    format: image.texture_descriptor.format,
+                },
+            ))
+            .observe(store_canvas_readback);
+    }
+    true
+}
+
+/// Reads back `snapshot_layers` into one state of the [`CanvasHistory`]. Returns false if any of
+/// them isn't ready to be read.
+pub(crate) fn spawn_canvas_snapshot(
+    commands: &mut Commands,
+    layers: &CanvasLayers,
+    snapshot_layers: &[&CanvasLayer],
+    images: &Assets<Image>,
+) -> bool {
+    let snapshot = commands
+        .spawn(PendingCanvasSnapshot {
+            layers: snapshot_layers
+                .iter()
+                .map(|layer| (layer.id(), None))
+                .collect(),
+        })
+        .id();
+    let all_read = snapshot_layers.iter().enumerate().all(|(index, layer)| {
+        spawn_canvas_readback(
+            commands,
+            layers,
+            layer,
+            images,
+            CanvasReadbackPurpose::SnapshotLayer { snapshot, index },
+        )
+    });
+    if !all_read {
+        // The readbacks already on their way find the snapshot gone and are dropped
+        commands.entity(snapshot).despawn();
+    }
+    all_read
+}
+
+pub(crate) fn request_canvas_save(
+    mut commands: Commands,
+    mut requests: EventReader<SaveCanvas>,
+    layers: Res<CanvasLayers>,
+    images: Res<Assets<Image>>,
+    mut saved: EventWriter<CanvasSaved>,
+) {
+    for request in requests.read() {
+        let save = commands
+            .spawn(PendingCanvasSave {
+                path: request.path.clone(),
+                layers: layers
+                    .iter()
+                    .map(|layer| (LayerInfo::of(layer), None))
+                    .collect(),
+            })
+            .id();
+        let all_read = layers.iter().enumerate().all(|(index, layer)| {
+            spawn_canvas_readback(
+                &mut commands,
+                &layers,
+                layer,
+                &images,
+                CanvasReadbackPurpose::SaveLayer { save, index },
+            )
+        });
+        if !all_read {
+            // The readbacks already on their way find the save gone and are dropped
+            commands.entity(save).despawn();
+            let error = "the canvas hasn't been created yet".to_string();
+            error!(
+                "Couldn't save the canvas to {}: {error}",
+                request.path.display()
+            );
+            saved.write(CanvasSaved {
+                path: request.path.clone(),
+                result: Err(error),
+            });
+        }
+    }
+}
+
+/// Where finished canvas readbacks go.
+#[derive(SystemParam)]
+struct CanvasReadbackTargets<'w, 's> {
+    history: ResMut<'w, CanvasHistory>,
+    fills: ResMut<'w, PendingFills>,
+    layers: Res<'w, CanvasLayers>,
+    images: ResMut<'w, Assets<Image>>,
+    saves: Query<'w, 's, &'static mut PendingCanvasSave>,
+    snapshots: Query<'w, 's, &'static mut PendingCanvasSnapshot>,
+    saved: EventWriter<'w, CanvasSaved>,
+}
+
+fn store_canvas_readback(
+    trigger: Trigger<ReadbackComplete>,
+    readbacks: Query<&CanvasChannelReadback>,
+    mut pending: Query<&mut PendingCanvasReadback>,
+    mut commands: Commands,
+    targets: CanvasReadbackTargets,
+) {
+    let CanvasReadbackTargets {
+        mut history,
+        mut fills,
+        layers,
+        mut images,
+        mut saves,
+        mut snapshots,
+        mut saved,
+    } = targets;
+    let entity = trigger.target();
+    let Ok(readback) = readbacks.get(entity) else {
+        return;
+    };
+    // A readback repeats every frame until its entity is gone
+    commands.entity(entity).despawn();
+    let Ok(mut pending_readback) = pending.get_mut(readback.pending) else {
+        return;
+    };
+
+    let texels =
+        unpad_texels(&trigger.event().0, readback.size, readback.format).map(|data| CanvasTexels {
+            size: readback.size,
+            format: readback.format,
+            data,
+        });
+    match readback.channel {
+        CanvasChannel::Emission => pending_readback.emission = Some(texels),
+        CanvasChannel::Albedo => pending_readback.albedo = Some(texels),
+    }
+
+    if pending_readback.emission.is_none() || pending_readback.albedo.is_none() {
+        return;
+    }
+    let (Some(emission), Some(albedo)) = (
+        pending_readback.emission.take(),
+        pending_readback.albedo.take(),
+    ) else {
+        return;
+    };
+    commands.entity(readback.pending).despawn();
+
+    match pending_readback.purpose {
+        CanvasReadbackPurpose::SaveLayer { save, index } => {
+            let Ok(mut pending_save) = saves.get_mut(save) else {
+                return;
+            };
+            let info = pending_save.layers[index].0.clone();
+            pending_save.layers[index].1 = Some(emission.and_then(|emission| {
+                Ok(CanvasFileLayer {
+                    info: Some(info),
+                    emission: Some(emission.to_image()?),
+                    albedo: albedo?.to_image()?,
+                })
+            }));
+            if pending_save.layers.iter().any(|(_, layer)| layer.is_none()) {
+                return;
+            }
+            commands.entity(save).despawn();
+
+            let path = pending_save.path.clone();
+            let result = pending_save
+                .layers
+                .drain(..)
+                .filter_map(|(_, layer)| layer)
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|file_layers| write_canvas(&path, &file_layers));
+            if let Err(error) = &result {
+                error!("Couldn't save the canvas to {}: {error}", path.display());
+            }
+            saved.write(CanvasSaved { path, result });
+        }
+        CanvasReadbackPurpose::SnapshotLayer { snapshot, index } => {
+            let Ok(mut pending_snapshot) = snapshots.get_mut(snapshot) else {
+                return;
+            };
+            let layer = pending_snapshot.layers[index].0;
+            pending_snapshot.layers[index].1 = Some(emission.and_then(|emission| {
+                Ok(LayerSnapshot {
+                    layer,
+                    emission,
+                    albedo: albedo?,
+                })
+            }));
+            if pending_snapshot
+                .layers
+                .iter()
+                .any(|(_, snapshot)| snapshot.is_none())
+            {
+                return;
+            }
+            commands.entity(snapshot).despawn();
+
+            let result = pending_snapshot
+                .layers
+                .drain(..)
+                .filter_map(|(_, snapshot)| snapshot)
+                .collect::<Result<Vec<_>, _>>();
+            match result {
+                Ok(snapshots) => history.push(snapshots),
+                Err(error) => {
+                    history.snapshot_failed();
+                    error!("Couldn't snapshot the canvas for undo: {error}");
+                }
+            }
+        }
+        CanvasReadbackPurpose::Fill {
+            layer,
+            seed,
+            emission: fill_emission,
+            albedo: fill_albedo,
+        } => {
+            let result = emission.and_then(|mut emission| {
+                let mut albedo = albedo?;
+                flood_fill(&mut emission, &mut albedo, seed, fill_emission, fill_albedo)?;
+                let filled = layers
+                    .get(layer)
+                    .ok_or_else(|| "the layer was removed".to_string())?;
+                restore_layer(&mut images, filled, &emission, &albedo)?;
+                // Fills don't end like strokes do, so they go into the undo history here
+                history.record(vec![LayerSnapshot {
+                    layer,
+                    emission,
+                    albedo,
+                }]);
+                Ok(())
+            });
+            fills.finish(layer);
+            if let Err(error) = result {
+                error!("Couldn't fill the canvas: {error}");
+            }
+        }
+    }
+}
+
+fn write_canvas(path: &Path, file_layers: &[CanvasFileLayer]) -> Result<(), String> {
+    let mut list = String::new();
+    for (index, layer) in file_layers.iter().enumerate() {
+        let layer_path = canvas_layer_path(path, index);
+        // The albedo is stored linearly on the GPU as well, so its bytes go into the PNG as they are
+        DynamicImage::ImageRgba32F(layer.albedo.clone())
+            .to_rgba8()
+            .save(&layer_path)
+            .map_err(|error| error.to_string())?;
+        if let Some(emission) = &layer.emission {
+            DynamicImage::ImageRgba32F(emission.clone())
+                .save(canvas_emission_path(&layer_path))
+                .map_err(|error| error.to_string())?;
+        }
+        if let Some(info) = &layer.info {
+            list.push_str(&info.to_line());
+            list.push('\n');
+        }
+    }
+    std::fs::write(canvas_layers_path(path), list).map_err(|error| error.to_string())
+}
+
+pub(crate) fn load_canvas(
+    mut requests: EventReader<LoadCanvas>,
+    mut layers: ResMut<CanvasLayers>,
+    mut images: ResMut<Assets<Image>>,
+    mut loaded: EventWriter<CanvasLoaded>,
+) {
+    for request in requests.read() {
+        let result = read_canvas(&request.path)
+            .and_then(|file_layers| replace_layers(&mut layers, &mut images, &file_layers));
+        if let Err(error) = &result {
+            error!(
+                "Couldn't load the canvas from {}: {error}",
+                request.path.display()
+            );
+        }
+        loaded.write(CanvasLoaded {
+            path: request.path.clone(),
+            result,
+        });
+    }
+}
+
+fn read_canvas(path: &Path) -> Result<Vec<CanvasFileLayer>, String> {
+    let list_path = canvas_layers_path(path);
+    let infos = if list_path.exists() {
+        std::fs::read_to_string(&list_path)
+            .map_err(|error| error.to_string())?
+            .lines()
+            .enumerate()
+            .map(|(line, text)| {
+                LayerInfo::from_line(text).map(Some).ok_or_else(|| {
+                    format!("line {} of {} isn't a layer", line + 1, list_path.display())
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![None]
+    };
+
+    infos
+        .into_iter()
+        .enumerate()
+        .map(|(index, info)| {
+            let layer_path = canvas_layer_path(path, index);
+            let albedo = image::open(&layer_path)
+                .map_err(|error| error.to_string())?
+                .to_rgba32f();
+            let emission_path = canvas_emission_path(&layer_path);
+            let emission = if emission_path.exists() {
+                Some(
+                    image::open(&emission_path)
+                        .map_err(|error| error.to_string())?
+                        .to_rgba32f(),
+                )
+            } else {
+                None
+            };
+            Ok(CanvasFileLayer {
+                info,
+                emission,
+                albedo,
+            })
+        })
+        .collect()
+}
+
+/// Replaces the layers of the canvas with the ones read from a file, or just the active layer for an
+/// image that wasn't saved with its layers.
+fn replace_layers(
+    layers: &mut CanvasLayers,
+    images: &mut Assets<Image>,
+    file_layers: &[CanvasFileLayer],
+) -> Result<(), String> {
+    if let [
+        CanvasFileLayer {
+            info: None,
+            emission,
+            albedo,
+        },
+    ] = file_layers
+    {
+        return replace_layer_paint(images, layers.active(), emission.as_ref(), albedo);
+    }
+    if file_layers.is_empty() {
+        return Err("the canvas has no layers".to_string());
+    }
+
+    // The bottom layer is kept and the rest are made again, so the stack matches the file
+    layers.set_active(0);
+    while layers.remove(layers.len() - 1) {}
+    for (index, file_layer) in file_layers.iter().enumerate() {
+        if index > 0 {
+            layers
+                .add(String::new(), images)
+                .ok_or_else(|| "the canvas hasn't been created yet".to_string())?;
+        }
+        let Some(layer) = layers.iter_mut().nth(index) else {
+            continue;
+        };
+        if let Some(info) = &file_layer.info {
+            info.apply(layer);
+        }
+        replace_layer_paint(
+            images,
+            layer,
+            file_layer.emission.as_ref(),
+            &file_layer.albedo,
+        )?;
+    }
+    Ok(())
+}
+
+/// Uploads a layer read from a file into both sides of `layer`'s ping pong, so it doesn't matter
+/// which is read next.
+fn replace_layer_paint(
+    images: &mut Assets<Image>,
+    layer: &CanvasLayer,
+    emission: Option<&Rgba32FImage>,
+    albedo: &Rgba32FImage,
+) -> Result<(), String> {
+    for side in layer.sides() {
+        replace_image(images, &side.emission, emission)?;
+        replace_image(images, &side.albedo, Some(albedo))?;
+    }
+    Ok(())
+}
+
+/// Puts a copy of the layer back into both sides of its ping pong.
+fn restore_layer(
+    images: &mut Assets<Image>,
+    layer: &CanvasLayer,
+    emission: &CanvasTexels,
+    albedo: &CanvasTexels,
+) -> Result<(), String> {
+    for side in layer.sides() {
+        restore_texels(images, &side.emission, Some(emission))?;
+        restore_texels(images, &side.albedo, Some(albedo))?;
+    }
+    Ok(())
+}
+
+/// Puts `texels` back into the image behind `handle`, or clears it if there are none.
+pub(crate) fn restore_texels(
+    images: &mut Assets<Image>,
+    handle: &Handle<Image>,
+    texels: Option<&CanvasTexels>,
+) -> Result<(), String> {
+    let image = images
+        .get_mut(handle)
+        .ok_or_else(|| "the canvas hasn't been created yet".to_string())?;
+    if let Some(texels) = texels
+        && texels.size == image.size()
+        && texels.format == image.texture_descriptor.format
+    {
+        image.data = Some(texels.data.clone());
+        return Ok(());
+    }
+
+    // The canvas was resized since, so the texels are stretched like a loaded image
+    let source = texels.map(CanvasTexels::to_image).transpose()?;
+    replace_image(images, handle, source.as_ref())
+}
+
+/// Uploads `source` into the image behind `handle`, stretched to its size, or clears it if there's no `source`.
+fn replace_image(
+    images: &mut Assets<Image>,
+    handle: &Handle<Image>,
+    source: Option<&Rgba32FImage>,
+) -> Result<(), String> {
+    let image = images
+        .get_mut(handle)
+        .ok_or_else(|| "the canvas hasn't been created yet".to_string())?;
+    let size = image.size();
+    let texels = match source {
+        Some(source) if source.dimensions() == (size.x, size.y) => source.clone(),
+        Some(source) => image::imageops::resize(source, size.x, size.y, FilterType::Triangle),
+        None => Rgba32FImage::new(size.x, size.y),
+    };
+    image.data = Some(encode_texels(
+        texels.as_raw(),
+        image.texture_descriptor.format,
+    )?);
+    Ok(())
+}
+
+/// Turns linear rgba floats into texels for the GPU, the reverse of `texels_to_f32`.
+pub(crate) fn encode_texels(texels: &[f32], format: TextureFormat) -> Result<Vec<u8>, String> {
+    Ok(match format {
+        TextureFormat::Rgba16Float => texels
+            .iter()
+            .flat_map(|&texel| f16::from_f32(texel).to_le_bytes())
+            .collect(),
+        TextureFormat::Rgba32Float => texels
+            .iter()
+            .flat_map(|&texel| texel.to_le_bytes())
+            .collect(),
+        TextureFormat::Rgba8Unorm => texels
+            .iter()
+            .map(|&texel| (texel.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect(),
+        _ => return Err(format!("a canvas stored as {format:?} can't be loaded")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension},
+    };
+
+    use super::*;
+    use crate::CanvasGBuffer;
+
+    const SIZE: u32 = 2;
+
+    fn empty_gbuffer(images: &mut Assets<Image>) -> CanvasGBuffer {
+        let mut empty = |format: TextureFormat| {
+            let zero = vec![0; format.block_copy_size(None).unwrap() as usize];
+            images.add(Image::new_fill(
+                Extent3d {
+                    width: SIZE,
+                    height: SIZE,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                &zero,
+                format,
+                RenderAssetUsages::default(),
+            ))
+        };
+        CanvasGBuffer {
+            emission: empty(TextureFormat::Rgba16Float),
+            albedo: empty(TextureFormat::Rgba8Unorm),
+        }
+    }
+
+    fn single_layer_canvas(images: &mut Assets<Image>) -> CanvasLayers {
+        CanvasLayers::new(empty_gbuffer(images), empty_gbuffer(images))
+    }
+
+    /// The paint of the layer at `index`, different for each layer. The albedo is in steps of 1/255
+    /// so the PNG keeps it exactly, and the emission is brighter than 1.0 in steps a half float keeps.
+    fn paint(index: u32) -> (Rgba32FImage, Rgba32FImage) {
+        let emission = Rgba32FImage::from_fn(SIZE, SIZE, |x, y| {
+            image::Rgba([2.0 + index as f32, 0.5 * (x + y) as f32, 0.25, 1.0])
+        });
+        let albedo = Rgba32FImage::from_fn(SIZE, SIZE, |x, y| {
+            let level = |steps: u32| steps as f32 * 51.0 / 255.0;
+            image::Rgba([level(x + 2 * y), level(index), 1.0 - level(x), 1.0])
+        });
+        (emission, albedo)
+    }
+
+    /// The image behind `handle`, as a readback of it would see it.
+    fn read_back(images: &Assets<Image>, handle: &Handle<Image>) -> Rgba32FImage {
+        let image = images.get(handle).unwrap();
+        CanvasTexels {
+            size: image.size(),
+            format: image.texture_descriptor.format,
+            data: image.data.clone().unwrap(),
+        }
+        .to_image()
+        .unwrap()
+    }
+
+    /// The paint on `layer`, as a saved canvas would read it back.
+    fn file_layer(images: &Assets<Image>, layer: &CanvasLayer) -> CanvasFileLayer {
+        let [side, _] = layer.sides();
+        CanvasFileLayer {
+            info: Some(LayerInfo::of(layer)),
+            emission: Some(read_back(images, &side.emission)),
+            albedo: read_back(images, &side.albedo),
+        }
+    }
+
+    fn assert_close(actual: &Rgba32FImage, expected: &Rgba32FImage) {
+        for (actual, expected) in actual.pixels().zip(expected.pixels()) {
+            for (actual, expected) in actual.0.iter().zip(expected.0) {
+                assert!(
+                    (actual - expected).abs() < 1e-3,
+                    "{actual} isn't {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn two_layer_canvas_round_trips() {
+        let mut images = Assets::<Image>::default();
+        let mut layers = single_layer_canvas(&mut images);
+        layers.add("Glass", &mut images).unwrap();
+        let infos = [
+            LayerInfo {
+                name: "Base".to_string(),
+                visible: true,
+                blend: LayerBlend::Normal,
+                role: LayerRole::Emitter,
+            },
+            LayerInfo {
+                name: "Glass".to_string(),
+                visible: false,
+                blend: LayerBlend::Multiply,
+                role: LayerRole::Albedo,
+            },
+        ];
+        let paints = [paint(0), paint(1)];
+        for ((layer, info), (emission, albedo)) in layers.iter_mut().zip(&infos).zip(&paints) {
+            info.apply(layer);
+            replace_layer_paint(&mut images, layer, Some(emission), albedo).unwrap();
+        }
+
+        let directory =
+            std::env::temp_dir().join(format!("canvas_round_trip_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("canvas.png");
+        let saved: Vec<_> = layers
+            .iter()
+            .map(|layer| file_layer(&images, layer))
+            .collect();
+        write_canvas(&path, &saved).unwrap();
+
+        // Loaded into a canvas of one layer, which has to grow to fit
+        let mut loaded = single_layer_canvas(&mut images);
+        let read = read_canvas(&path);
+        std::fs::remove_dir_all(&directory).unwrap();
+        replace_layers(&mut loaded, &mut images, &read.unwrap()).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        for ((layer, info), (emission, albedo)) in loaded.iter().zip(&infos).zip(&paints) {
+            assert_eq!(&LayerInfo::of(layer), info);
+            for side in layer.sides() {
+                assert_close(&read_back(&images, &side.emission), emission);
+                assert_close(&read_back(&images, &side.albedo), albedo);
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    prelude::*,
+    render::{
+        gpu_readback::{Readback, ReadbackComplete},
+        render_resource::TextureFormat,
+    },
+};
+use half::f16;
+use image::{DynamicImage, Rgba, Rgba32FImage, RgbaImage};
+
+use crate::{PostProcessSettings, RaymarchImages};
+
+// wgpu pads every row of a texture copy to this many bytes
+const COPY_BYTES_PER_ROW_ALIGNMENT: usize = 256;
+
+/// Saves the GI output of the frame this is sent in to `path`.
+///
+/// The file format follows the extension. EXR files keep the linear HDR values, anything else is
+/// clamped to LDR and stored in sRGB. The output is what the GI passes write, before the camera's
+/// tonemapping.
+#[derive(Event, Clone, Debug)]
+pub struct SaveGiOutput {
+    pub path: PathBuf,
+    /// The GI camera whose output is saved, the camera that paints when `None`.
+    pub camera: Option<Entity>,
+}
+
+impl SaveGiOutput {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            camera: None,
+        }
+    }
+
+    /// Saves the output of `camera` instead of the camera that paints.
+    pub fn of_camera(mut self, camera: Entity) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+}
+
+/// Sent once the file of a [`SaveGiOutput`] has been written, or couldn't be.
+#[derive(Event, Clone, Debug)]
+pub struct GiOutputSaved {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+}
+
+/// A readback of the GI output that's waiting to be written to `path`.
+#[derive(Component)]
+struct PendingGiSave {
+    path: PathBuf,
+    size: UVec2,
+    format: TextureFormat,
+}
+
+pub(crate) fn request_gi_readback(
+    mut commands: Commands,
+    mut requests: EventReader<SaveGiOutput>,
+    views: Query<(Entity, &RaymarchImages, Has<PostProcessSettings>)>,
+    images: Res<Assets<Image>>,
+    mut saved: EventWriter<GiOutputSaved>,
+) {
+    for request in requests.read() {
+        let Some((_, raymarch_images, _)) = views.iter().find(|&(camera, _, paints)| {
+            request
+                .camera
+                .map_or(paints, |requested| requested == camera)
+        }) else {
+            report_failed_save(
+                &mut saved,
+                &request.path,
+                "there's no GI camera to save the output of".to_string(),
+            );
+            continue;
+        };
+        // The ping pong has already been flipped this frame, so this is the image about to be rendered
+        let target = raymarch_images.target();
+        let Some(image) = images.get(target) else {
+            report_failed_save(
+                &mut saved,
+                &request.path,
+                "the GI output image doesn't exist".to_string(),
+            );
+            continue;
+        };
+        commands
+            .spawn((
+                Readback::texture(target.clone()),
+                PendingGiSave {
+                    path: request.path.clone(),
+                    size: image.size(),
+                    format: image.texture_descriptor.format,
+                },
+            ))
+            .observe(save_readback);
+    }
+}
+
+/// Reports a save that failed before its readback could be requested.
+fn report_failed_save(saved: &mut EventWriter<GiOutputSaved>, path: &Path, error: String) {
+    error!("Couldn't save the GI output to {}: {error}", path.display());
+    saved.write(GiOutputSaved {
+        path: path.to_path_buf(),
+        result: Err(error),
+    });
+}
+
+fn save_readback(
+    trigger: Trigger<ReadbackComplete>,
+    pending: Query<&PendingGiSave>,
+    mut commands: Commands,
+    mut saved: EventWriter<GiOutputSaved>,
+) {
+    let entity = trigger.target();
+    let Ok(pending) = pending.get(entity) else {
+        return;
+    };
+    // A readback repeats every frame until its entity is gone
+    commands.entity(entity).despawn();
+
+    let result = decode_texels(&trigger.event().0, pending.size, pending.format)
+        .and_then(|texels| write_image(&pending.path, pending.size, texels));
+    if let Err(error) = &result {
+        error!(
+            "Couldn't save the GI output to {}: {error}",
+            pending.path.display()
+        );
+    }
+    saved.write(GiOutputSaved {
+        path: pending.path.clone(),
+        result,
+    });
+}
+
+/// Turns the texels read back from the GPU into linear rgba floats.
+fn decode_texels(data: &[u8], size: UVec2, format: TextureFormat) -> Result<Vec<f32>, String> {
+    texels_to_f32(&unpad_texels(data, size, format)?, format)
+}
+
+/// Strips the padding the GPU adds to the end of every row of a readback.
+pub(crate) fn unpad_texels(
+    data: &[u8],
+    size: UVec2,
+    format: TextureFormat,
+) -> Result<Vec<u8>, String> {
+    let texel_size = format
+        .block_copy_size(None)
+        .ok_or_else(|| unsupported_format(format))? as usize;
+    let row_size = size.x as usize * texel_size;
+    let padded_row_size = row_size.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+    let stride = if data.len() >= padded_row_size * size.y as usize {
+        padded_row_size
+    } else {
+        row_size
+    };
+    if data.len() < stride * (size.y as usize).saturating_sub(1) + row_size {
+        return Err(format!(
+            "expected {}x{} texels but only got {} bytes",
+            size.x,
+            size.y,
+            data.len()
+        ));
+    }
+
+    Ok(data
+        .chunks(stride)
+        .take(size.y as usize)
+        .flat_map(|row| &row[..row_size])
+        .copied()
+        .collect())
+}
+
+/// Turns tightly packed texels into linear rgba floats.
+pub(crate) fn texels_to_f32(data: &[u8], format: TextureFormat) -> Result<Vec<f32>, String> {
+    Ok(match format {
+        TextureFormat::Rgba16Float => data
+            .chunks_exact(2)
+            .map(|bytes| f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+            .collect(),
+        TextureFormat::Rgba32Float => data
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect(),
+        TextureFormat::Rgba8Unorm => data.iter().map(|&byte| byte as f32 / 255.0).collect(),
+        _ => return Err(unsupported_format(format)),
+    })
+}
+
+pub(crate) fn unsupported_format(format: TextureFormat) -> String {
+    format!("texels stored as {format:?} can't be converted")
+}
+
+fn write_image(path: &Path, size: UVec2, texels: Vec<f32>) -> Result<(), String> {
+    let hdr = Rgba32FImage::from_raw(size.x, size.y, texels)
+        .ok_or_else(|| "the GI output doesn't match its size".to_string())?;
+
+    let is_exr = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+    let result = if is_exr {
+        DynamicImage::ImageRgba32F(hdr).save(path)
+    } else {
+        let ldr = RgbaImage::from_fn(size.x, size.y, |x, y| {
+            let [red, green, blue, _] = hdr.get_pixel(x, y).0;
+            Rgba(Srgba::from(LinearRgba::rgb(red, green, blue)).to_u8_array())
+        });
+        ldr.save(path)
+    };
+    result.map_err(|error| error.to_string())
+}
@@ -0,0 +1,649 @@
+use bevy::{
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    ecs::{query::QueryItem, system::SystemParam},
+    prelude::*,
+    render::{
+        diagnostic::RecordDiagnostics,
+        extract_component::ExtractComponent,
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{
+            binding_types::{texture_2d, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::{CachedTexture, FallbackImage, GpuImage, TextureCache},
+    },
+};
+
+use crate::{
+    CASCADE_SHADER_ASSET_PATH, CanvasImages, ComputeOutput, GiBackend, GiMode, GiTextureFormats,
+    RaymarchImages, RaymarchSettings, SkyLight, distance_field::DistanceFieldImage,
+    scene::SceneTextures,
+};
+
+// Cascades hold accumulated radiance, so they get more precision than the canvas does.
+const CASCADE_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Settings for the radiance cascades GI pass.
+///
+/// Cascade 0 places a probe every 2 pixels with 4 rays each. Every level above it doubles the
+/// probe spacing, the angular resolution and the interval length, so each cascade takes half the
+/// texels of the one below it. The cascades reach `base_interval * (2^cascade_count - 1)` pixels
+/// out, and rays that get past the top one without hitting anything see the sky.
+///
+/// Every 2D camera with these settings gets GI of its own, lighting the same canvas with its own
+/// settings and textures. See [`GiOutput`](crate::GiOutput) for how its output is shown.
+#[derive(Component, Clone, Copy, ExtractComponent)]
+#[require(SkyLight)]
+pub struct CascadeSettings {
+    pub cascade_count: u32,
+    /// Length in pixels of the interval traced by cascade 0.
+    pub base_interval: f32,
+    /// Maximum number of texture samples taken along a single interval. Steps never go past the
+    /// nearest surface, so an interval that grazes one may run out of them before its end, and then
+    /// passes on the light of the cascade above as if nothing lay in the rest of it.
+    pub max_steps: u32,
+    /// How each ray picks up the light gathered by the cascade above it.
+    pub merge: CascadeMerge,
+    /// How much of the light reaching a surface it passes on to the rest of the canvas, read back from
+    /// last frame's output like [`RaymarchSettings::bounce_strength`]. 0.0 only gathers the light
+    /// coming straight from emitters.
+    pub bounce_strength: f32,
+}
+
+impl Default for CascadeSettings {
+    fn default() -> Self {
+        Self {
+            cascade_count: 9,
+            base_interval: 4.0,
+            max_steps: 32,
+            merge: CascadeMerge::default(),
+            bounce_strength: 0.0,
+        }
+    }
+}
+
+/// How a ray of one cascade merges in the rays of the cascade above it, where its interval ends.
+///
+/// The probes of the cascade above are twice as far apart, so the end of a ray rarely lines up
+/// with one of them. The strategies trade the ringing and leaking this causes against cost.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CascadeMerge {
+    /// Merges the rays of the closest probe above. The cheapest, but the jumps between probes show
+    /// up as rings around lights.
+    Nearest = 0,
+    /// Interpolates between the four surrounding probes above. Smooth, but the light they gathered
+    /// leaks through walls that lie between them and the end of this ray.
+    #[default]
+    Bilinear = 1,
+    /// Traces a ray to each of the four surrounding probes above and merges it with that probe alone,
+    /// so walls in between block what they should. Four times the marching of `Bilinear`.
+    BilinearFix = 2,
+    /// Merges the same light as `Bilinear`, but each cascade above is first averaged down to one
+    /// texel for every ray of the cascade below, so a ray reads one texel from each probe above instead
+    /// of two. Takes an extra pass per cascade to average it.
+    PreAveraged = 3,
+}
+
+impl CascadeMerge {
+    pub const ALL: [Self; 4] = [
+        Self::Nearest,
+        Self::Bilinear,
+        Self::BilinearFix,
+        Self::PreAveraged,
+    ];
+}
+
+/// The uniform handed to each cascade level, the settings plus the level being rendered. The sky is
+/// the camera's [`SkyLight`], which rays that leave the canvas and rays of the top cascade that hit
+/// nothing see.
+#[derive(Clone, Copy, ShaderType)]
+struct CascadeLevelUniform {
+    resolution: Vec2,
+    cascade_index: u32,
+    cascade_count: u32,
+    base_interval: f32,
+    max_steps: u32,
+    merge: u32,
+    bounce_strength: f32,
+    /// Where last frame's output is read from, see [`RaymarchSettings::scroll`].
+    scroll: IVec2,
+    sky: SkyLight,
+}
+
+/// The uniforms of every cascade of every GI camera, one after the other.
+#[derive(Resource, Default)]
+pub(crate) struct CascadeUniforms {
+    buffer: DynamicUniformBuffer<CascadeLevelUniform>,
+}
+
+/// The cascades of one GI camera, and where their uniforms are in [`CascadeUniforms`].
+#[derive(Component, Default)]
+pub(crate) struct CascadeTextures {
+    cascades: Vec<CachedTexture>,
+    /// With [`CascadeMerge::PreAveraged`], the cascade above each cascade but the top one, averaged
+    /// down to a texel for every ray of the cascade it's merged into. Empty otherwise.
+    averaged: Vec<CachedTexture>,
+    offsets: Vec<u32>,
+}
+
+/// What [`prepare_cascades`] allocates the cascades from and uploads their uniforms with.
+#[derive(SystemParam)]
+pub(crate) struct CascadeAllocator<'w> {
+    render_device: Res<'w, RenderDevice>,
+    render_queue: Res<'w, RenderQueue>,
+    texture_cache: ResMut<'w, TextureCache>,
+    uniforms: ResMut<'w, CascadeUniforms>,
+}
+
+pub(crate) fn prepare_cascades(
+    mut commands: Commands,
+    views: Query<(Entity, &CascadeSettings, &SkyLight, &RaymarchSettings), With<RaymarchImages>>,
+    canvas_images: Option<Res<CanvasImages>>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    mut allocator: CascadeAllocator,
+) {
+    let CascadeAllocator {
+        render_device,
+        render_queue,
+        texture_cache,
+        uniforms,
+    } = &mut allocator;
+    let Some(canvas) = canvas_images.and_then(|images| gpu_images.get(&images.target().albedo))
+    else {
+        return;
+    };
+    let width = canvas.texture.width();
+    let height = canvas.texture.height();
+    uniforms.buffer.clear();
+
+    // Every GI camera gets raymarch settings too, and the plugin keeps the scroll up to date on them
+    for (view, settings, sky, raymarch_settings) in &views {
+        let cascade_count = settings.cascade_count.max(1);
+
+        let mut textures = CascadeTextures::default();
+        for cascade_index in 0..cascade_count {
+            let offset = uniforms.buffer.push(&CascadeLevelUniform {
+                resolution: Vec2::new(width as f32, height as f32),
+                cascade_index,
+                cascade_count,
+                base_interval: settings.base_interval,
+                max_steps: settings.max_steps.max(1),
+                merge: settings.merge as u32,
+                bounce_strength: settings.bounce_strength,
+                scroll: raymarch_settings.scroll,
+                sky: *sky,
+            });
+            textures.offsets.push(offset);
+
+            let probes = probe_count(width, height, cascade_index);
+            textures.cascades.push(texture_cache.get(
+                render_device,
+                cascade_texture_descriptor(
+                    "radiance_cascade_texture",
+                    probes * probe_block(cascade_index),
+                ),
+            ));
+            // The cascade above averaged down holds this cascade's rays at each of its own probes
+            if settings.merge == CascadeMerge::PreAveraged && cascade_index + 1 < cascade_count {
+                let upper_probes = probe_count(width, height, cascade_index + 1);
+                textures.averaged.push(texture_cache.get(
+                    render_device,
+                    cascade_texture_descriptor(
+                        "radiance_cascade_averaged_texture",
+                        upper_probes * probe_block(cascade_index),
+                    ),
+                ));
+            }
+        }
+        commands.entity(view).insert(textures);
+    }
+
+    uniforms.buffer.write_buffer(render_device, render_queue);
+}
+
+/// How many probes `cascade_index` places across a canvas of `width` by `height` pixels, including
+/// the ones partly past its edges.
+fn probe_count(width: u32, height: u32, cascade_index: u32) -> UVec2 {
+    let spacing = 2 << cascade_index;
+    UVec2::new(width.div_ceil(spacing), height.div_ceil(spacing))
+}
+
+/// The texels each probe of `cascade_index` stores its rays in, matching `probe_block` in
+/// `cascades.wgsl`.
+fn probe_block(cascade_index: u32) -> UVec2 {
+    UVec2::new(
+        1 << ((cascade_index + 3) / 2),
+        1 << ((cascade_index + 2) / 2),
+    )
+}
+
+fn cascade_texture_descriptor(label: &'static str, size: UVec2) -> TextureDescriptor<'static> {
+    TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: CASCADE_TEXTURE_FORMAT,
+        // Written as a storage texture by the compute passes
+        usage: TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::TEXTURE_BINDING
+            | TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    }
+}
+
+/// Renders every cascade and resolves cascade 0, used when `GiMode::Cascades` is selected.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct CascadeLabel;
+
+#[derive(Default)]
+pub(crate) struct CascadeNode;
+
+impl ViewNode for CascadeNode {
+    type ViewQuery = (&'static RaymarchImages, &'static CascadeTextures);
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (raymarch_images, textures): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if *world.resource::<GiMode>() != GiMode::Cascades {
+            return Ok(());
+        }
+
+        let cascade_pipeline = world.resource::<CascadePipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let (Some(pipeline), Some(average_pipeline), Some(resolve_pipeline)) = (
+            pipeline_cache.get_render_pipeline(cascade_pipeline.pipeline_id),
+            pipeline_cache.get_render_pipeline(cascade_pipeline.average_pipeline_id),
+            pipeline_cache.get_render_pipeline(cascade_pipeline.resolve_pipeline_id),
+        ) else {
+            return Ok(());
+        };
+        // Falls back to the fragment passes until the compute pipelines are ready, or if they can't be built
+        let compute = cascade_pipeline
+            .compute
+            .as_ref()
+            .filter(|_| *world.resource::<GiBackend>() == GiBackend::Compute)
+            .and_then(|compute| {
+                Some((
+                    compute,
+                    pipeline_cache.get_compute_pipeline(compute.cascade_pipeline_id)?,
+                    pipeline_cache.get_compute_pipeline(compute.average_pipeline_id)?,
+                    pipeline_cache.get_compute_pipeline(compute.resolve_pipeline_id)?,
+                ))
+            });
+
+        let uniforms = world.resource::<CascadeUniforms>();
+        let Some(uniform_binding) = uniforms.buffer.binding() else {
+            return Ok(());
+        };
+        if textures.cascades.is_empty() {
+            return Ok(());
+        }
+
+        let scene_textures = world.resource::<SceneTextures>();
+        let (Some(scene), Some(previous_scene)) =
+            (scene_textures.gbuffer(), scene_textures.previous_gbuffer())
+        else {
+            return Ok(());
+        };
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let fallback_image = world.resource::<FallbackImage>();
+        let distance_view = &gpu_images
+            .get(&world.resource::<DistanceFieldImage>().image)
+            .unwrap()
+            .texture_view;
+
+        // Last frame's output, the one the sprite is showing, is what surfaces bounce back
+        let (dst, history) = if raymarch_images.ping {
+            (&raymarch_images.a, &raymarch_images.b)
+        } else {
+            (&raymarch_images.b, &raymarch_images.a)
+        };
+        // A camera that just got its GI has to wait for its images to reach the GPU
+        let (Some(dst_image), Some(history_image)) = (gpu_images.get(dst), gpu_images.get(history))
+        else {
+            return Ok(());
+        };
+        let dst_view = &dst_image.texture_view;
+
+        // Every pass reads the same textures but for the cascade it's handed
+        let render_device = render_context.render_device().clone();
+        let bind_group = |label: &'static str, cascade_view: &TextureView| {
+            render_device.create_bind_group(
+                label,
+                &cascade_pipeline.layout,
+                &BindGroupEntries::sequential((
+                    // Same as the raymarch pass, we read the canvas with the emitters and occluders drawn over it
+                    &scene.emission.default_view,
+                    &scene.albedo.default_view,
+                    cascade_view,
+                    uniform_binding.clone(),
+                    distance_view,
+                    &history_image.texture_view,
+                    &previous_scene.emission.default_view,
+                    &previous_scene.albedo.default_view,
+                )),
+            )
+        };
+
+        let backend = if compute.is_some() {
+            GiBackend::Compute
+        } else {
+            GiBackend::Fragment
+        };
+        let diagnostics = render_context.diagnostic_recorder();
+        let time_span = diagnostics.time_span(
+            render_context.command_encoder(),
+            backend.span_name(GiMode::Cascades),
+        );
+
+        // Cascades are rendered top-down so every level can merge the one above it, which has already been merged itself.
+        for (cascade_index, cascade) in textures.cascades.iter().enumerate().rev() {
+            // The top level has nothing to merge, so it gets the fallback image to keep the layout happy.
+            let upper = if textures.averaged.is_empty() {
+                textures.cascades.get(cascade_index + 1)
+            } else {
+                textures.averaged.get(cascade_index)
+            };
+            let upper_view =
+                upper.map_or(&fallback_image.d2.texture_view, |upper| &upper.default_view);
+            let cascade_bind_group = bind_group("cascade_bind_group", upper_view);
+            let offset = textures.offsets[cascade_index];
+
+            if let Some((compute, cascade_compute_pipeline, ..)) = compute {
+                let output_bind_group = render_device.create_bind_group(
+                    "cascade_output_bind_group",
+                    &compute.cascade_output.layout,
+                    &BindGroupEntries::single(&cascade.default_view),
+                );
+                dispatch(
+                    render_context,
+                    "cascade_compute_pass",
+                    cascade_compute_pipeline,
+                    [&cascade_bind_group, &output_bind_group],
+                    offset,
+                    &cascade.texture,
+                );
+            } else {
+                draw(
+                    render_context,
+                    "cascade_pass",
+                    pipeline,
+                    &cascade_bind_group,
+                    offset,
+                    &cascade.default_view,
+                );
+            }
+
+            // The cascade below merges this one averaged down
+            let Some(averaged) = cascade_index
+                .checked_sub(1)
+                .and_then(|below| textures.averaged.get(below))
+            else {
+                continue;
+            };
+            let average_bind_group =
+                bind_group("cascade_average_bind_group", &cascade.default_view);
+            if let Some((compute, _, average_compute_pipeline, _)) = compute {
+                let output_bind_group = render_device.create_bind_group(
+                    "cascade_average_output_bind_group",
+                    &compute.cascade_output.layout,
+                    &BindGroupEntries::single(&averaged.default_view),
+                );
+                dispatch(
+                    render_context,
+                    "cascade_average_compute_pass",
+                    average_compute_pipeline,
+                    [&average_bind_group, &output_bind_group],
+                    offset,
+                    &averaged.texture,
+                );
+            } else {
+                draw(
+                    render_context,
+                    "cascade_average_pass",
+                    average_pipeline,
+                    &average_bind_group,
+                    offset,
+                    &averaged.default_view,
+                );
+            }
+        }
+
+        // Finally, turn the merged cascade 0 into per pixel radiance
+        let resolve_bind_group = bind_group(
+            "cascade_resolve_bind_group",
+            &textures.cascades[0].default_view,
+        );
+        if let Some((compute, .., resolve_compute_pipeline)) = compute {
+            let output_bind_group = render_device.create_bind_group(
+                "cascade_resolve_output_bind_group",
+                &compute.resolve_output.layout,
+                &BindGroupEntries::single(dst_view),
+            );
+            dispatch(
+                render_context,
+                "cascade_resolve_compute_pass",
+                resolve_compute_pipeline,
+                [&resolve_bind_group, &output_bind_group],
+                textures.offsets[0],
+                &dst_image.texture,
+            );
+        } else {
+            draw(
+                render_context,
+                "cascade_resolve_pass",
+                resolve_pipeline,
+                &resolve_bind_group,
+                textures.offsets[0],
+                dst_view,
+            );
+        }
+
+        time_span.end(render_context.command_encoder());
+
+        Ok(())
+    }
+}
+
+/// Runs one fragment pass over every texel of `output`.
+fn draw(
+    render_context: &mut RenderContext,
+    label: &'static str,
+    pipeline: &RenderPipeline,
+    bind_group: &BindGroup,
+    offset: u32,
+    output: &TextureView,
+) {
+    let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: output,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Load,
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    render_pass.set_render_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[offset]);
+    render_pass.draw(0..3, 0..1);
+}
+
+/// Runs one compute pass over every texel of `output`, in the shader's 8x8 workgroups.
+fn dispatch(
+    render_context: &mut RenderContext,
+    label: &'static str,
+    pipeline: &ComputePipeline,
+    [bind_group, output_bind_group]: [&BindGroup; 2],
+    offset: u32,
+    output: &Texture,
+) {
+    let mut compute_pass =
+        render_context
+            .command_encoder()
+            .begin_compute_pass(&ComputePassDescriptor {
+                label: Some(label),
+                timestamp_writes: None,
+            });
+    compute_pass.set_pipeline(pipeline);
+    compute_pass.set_bind_group(0, bind_group, &[offset]);
+    compute_pass.set_bind_group(1, output_bind_group, &[]);
+    compute_pass.dispatch_workgroups(output.width().div_ceil(8), output.height().div_ceil(8), 1);
+}
+
+/// The compute variants of the cascade and resolve passes.
+struct CascadeComputePipelines {
+    /// Also used by the average pass, whose output has the same format.
+    cascade_output: ComputeOutput,
+    resolve_output: ComputeOutput,
+    cascade_pipeline_id: CachedComputePipelineId,
+    average_pipeline_id: CachedComputePipelineId,
+    resolve_pipeline_id: CachedComputePipelineId,
+}
+
+#[derive(Resource)]
+pub(crate) struct CascadePipeline {
+    layout: BindGroupLayout,
+    pipeline_id: CachedRenderPipelineId,
+    average_pipeline_id: CachedRenderPipelineId,
+    resolve_pipeline_id: CachedRenderPipelineId,
+    /// Only built when the GI output format can be written from a compute shader.
+    compute: Option<CascadeComputePipelines>,
+}
+
+impl FromWorld for CascadePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let formats = *world.resource::<GiTextureFormats>();
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "cascade_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                // Shared by the fragment and compute passes
+                ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                (
+                    // The emission channel of the canvas
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // The albedo and occlusion channels of the canvas, rays are traced against this
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // The cascade above the one being rendered, or cascade 0 when resolving
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // One uniform per cascade level, selected with a dynamic offset
+                    uniform_buffer::<CascadeLevelUniform>(true),
+                    // The distance field from the JFA passes
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Last frame's output, for the light surfaces bounce back
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Last frame's scene, to leave out the bounce where it changed
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+            ),
+        );
+
+        let compute_outputs = ComputeOutput::new(
+            render_device,
+            "cascade_output_layout",
+            CASCADE_TEXTURE_FORMAT,
+        )
+        .zip(ComputeOutput::new(
+            render_device,
+            "cascade_resolve_output_layout",
+            formats.radiance,
+        ));
+
+        let shader = world.load_asset(CASCADE_SHADER_ASSET_PATH);
+
+        let descriptor =
+            |label: &'static str, entry_point: &'static str, format| RenderPipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![layout.clone()],
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: shader.clone(),
+                    shader_defs: vec![],
+                    entry_point: entry_point.into(),
+                    targets: vec![Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                push_constant_ranges: vec![],
+                zero_initialize_workgroup_memory: false,
+            };
+        let compute_descriptor =
+            |label: &'static str, entry_point: &'static str, output: &ComputeOutput| {
+                ComputePipelineDescriptor {
+                    label: Some(label.into()),
+                    layout: vec![layout.clone(), output.layout.clone()],
+                    push_constant_ranges: vec![],
+                    shader: shader.clone(),
+                    shader_defs: output.shader_defs.clone(),
+                    entry_point: entry_point.into(),
+                    zero_initialize_workgroup_memory: false,
+                }
+            };
+        let cascade_descriptor = descriptor("cascade_pipeline", "cascade", CASCADE_TEXTURE_FORMAT);
+        let average_descriptor = descriptor(
+            "cascade_average_pipeline",
+            "average",
+            CASCADE_TEXTURE_FORMAT,
+        );
+        let resolve_descriptor =
+            descriptor("cascade_resolve_pipeline", "resolve", formats.radiance);
+
+        let pipeline_cache = world.resource_mut::<PipelineCache>();
+        let pipeline_id = pipeline_cache.queue_render_pipeline(cascade_descriptor);
+        let average_pipeline_id = pipeline_cache.queue_render_pipeline(average_descriptor);
+        let resolve_pipeline_id = pipeline_cache.queue_render_pipeline(resolve_descriptor);
+        let compute =
+            compute_outputs.map(|(cascade_output, resolve_output)| CascadeComputePipelines {
+                cascade_pipeline_id: pipeline_cache.queue_compute_pipeline(compute_descriptor(
+                    "cascade_compute_pipeline",
+                    "cascade_compute",
+                    &cascade_output,
+                )),
+                average_pipeline_id: pipeline_cache.queue_compute_pipeline(compute_descriptor(
+                    "cascade_average_compute_pipeline",
+                    "average_compute",
+                    &cascade_output,
+                )),
+                resolve_pipeline_id: pipeline_cache.queue_compute_pipeline(compute_descriptor(
+                    "cascade_resolve_compute_pipeline",
+                    "resolve_compute",
+                    &resolve_output,
+                )),
+                cascade_output,
+                resolve_output,
+            });
+
+        Self {
+            layout,
+            pipeline_id,
+            average_pipeline_id,
+            resolve_pipeline_id,
+            compute,
+        }
+    }
+}
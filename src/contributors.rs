@@ -0,0 +1,216 @@
+use bevy::{
+    core_pipeline::tonemapping::{DebandDither, Tonemapping},
+    prelude::*,
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        extract_resource::ExtractResource,
+        view::RenderLayers,
+    },
+};
+
+use crate::GiDomain;
+
+/// The render layer [`GiContributor`] entities are added to, so the GI albedo camera sees them.
+pub const GI_ALBEDO_LAYER: usize = 30;
+/// The render layer the emissive copies of [`GiContributor`] entities live on.
+pub const GI_EMISSION_LAYER: usize = 31;
+
+/// Makes a `Sprite` or `Mesh2d` take part in the GI.
+///
+/// Wherever the entity's alpha is above 0.5 it blocks light, and its colour becomes the albedo the
+/// GI lights. With [`GiEmission`] it gives off light as well.
+#[derive(Component, Clone, Debug, Default)]
+pub struct GiContributor {
+    pub emission: Option<GiEmission>,
+}
+
+impl GiContributor {
+    /// Blocks light and is lit, without giving off any.
+    pub fn occluder() -> Self {
+        Self::default()
+    }
+
+    /// Gives off `tint` times `intensity`, over the sprite's own image or the mesh's shape.
+    pub fn emissive(tint: Color, intensity: f32) -> Self {
+        Self {
+            emission: Some(GiEmission {
+                texture: None,
+                tint,
+                intensity,
+            }),
+        }
+    }
+
+    /// Uses `texture` for the emission instead of the sprite's image.
+    pub fn with_emissive_texture(mut self, texture: Handle<Image>) -> Self {
+        self.emission
+            .get_or_insert_with(|| GiEmission {
+                texture: None,
+                tint: Color::WHITE,
+                intensity: 1.0,
+            })
+            .texture = Some(texture);
+        self
+    }
+}
+
+/// The light a [`GiContributor`] gives off.
+#[derive(Clone, Debug)]
+pub struct GiEmission {
+    /// Falls back to the sprite's image, or an untextured mesh.
+    pub texture: Option<Handle<Image>>,
+    pub tint: Color,
+    /// Multiplies `tint`, anything above 1.0 relies on the HDR output.
+    pub intensity: f32,
+}
+
+/// The copy of a [`GiContributor`] that the GI emission camera renders, kept as its child.
+#[derive(Component)]
+pub struct GiEmissionProxy;
+
+/// Marks the cameras that render the [`GiContributor`] entities into the [`GiSceneImages`].
+#[derive(Component)]
+pub struct GiSceneCamera;
+
+/// What the GI scene cameras render each frame, before the scene pass draws the canvas over it.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct GiSceneImages {
+    /// Colour in rgb and coverage in alpha.
+    pub albedo: Handle<Image>,
+    pub emission: Handle<Image>,
+}
+
+/// Spawns the two cameras that render the GI scene. They render before `order`, the order of the
+/// camera the GI is attached to, so the GI passes read this frame's scene.
+pub(crate) fn spawn_gi_scene_cameras(
+    commands: &mut Commands,
+    images: &GiSceneImages,
+    order: isize,
+) {
+    for (image, layer, order) in [
+        (&images.albedo, GI_ALBEDO_LAYER, order - 2),
+        (&images.emission, GI_EMISSION_LAYER, order - 1),
+    ] {
+        commands.spawn((
+            Camera2d,
+            Camera {
+                order,
+                target: RenderTarget::Image(image.clone().into()),
+                clear_color: ClearColorConfig::Custom(Color::NONE),
+                // Emission can be brighter than 1.0
+                hdr: true,
+                ..Default::default()
+            },
+            // The scene pass reads raw colours and alpha, so nothing may blur or remap them
+            Msaa::Off,
+            Tonemapping::None,
+            DebandDither::Disabled,
+            RenderLayers::layer(layer),
+            GiSceneCamera,
+        ));
+    }
+}
+
+/// Keeps the GI scene cameras looking at the same part of the world the canvas covers.
+pub(crate) fn sync_gi_scene_cameras(
+    domain: Res<GiDomain>,
+    mut cameras: Query<(&mut Transform, &mut Projection), With<GiSceneCamera>>,
+) {
+    for (mut transform, mut projection) in &mut cameras {
+        *transform = Transform::from_translation(domain.center.extend(1000.0));
+        *projection = Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width: domain.size.x,
+                height: domain.size.y,
+            },
+            ..OrthographicProjection::default_2d()
+        });
+    }
+}
+
+/// Adds contributors to the GI albedo layer, on top of whatever layers they already render on.
+pub(crate) fn sync_contributor_layers(
+    mut commands: Commands,
+    added: Query<(Entity, Option<&RenderLayers>), Added<GiContributor>>,
+    mut removed: RemovedComponents<GiContributor>,
+    layers: Query<&RenderLayers>,
+) {
+    for (entity, render_layers) in &added {
+        let render_layers = render_layers.cloned().unwrap_or_default();
+        commands
+            .entity(entity)
+            .insert(render_layers.with(GI_ALBEDO_LAYER));
+    }
+    for entity in removed.read() {
+        if let Ok(render_layers) = layers.get(entity)
+            && let Ok(mut entity) = commands.get_entity(entity)
+        {
+            entity.insert(render_layers.clone().without(GI_ALBEDO_LAYER));
+        }
+    }
+}
+
+/// A contributor whose settings, sprite or mesh changed since its emissive copy was built.
+type ContributorChanged = Or<(Changed<GiContributor>, Changed<Sprite>, Changed<Mesh2d>)>;
+
+/// A contributor with the sprite or mesh its emissive copy is built from.
+type ContributorSources = (
+    Entity,
+    &'static GiContributor,
+    Option<&'static Sprite>,
+    Option<&'static Mesh2d>,
+    Option<&'static Children>,
+);
+
+/// Rebuilds the emissive copy of a contributor whenever it, its sprite or its mesh changes.
+pub(crate) fn sync_emission_proxies(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    contributors: Query<ContributorSources, ContributorChanged>,
+    proxies: Query<(), With<GiEmissionProxy>>,
+) {
+    for (entity, contributor, sprite, mesh, children) in &contributors {
+        for child in children.into_iter().flatten() {
+            if proxies.contains(*child) {
+                commands.entity(*child).despawn();
+            }
+        }
+
+        let Some(emission) = &contributor.emission else {
+            continue;
+        };
+        let tint = emission.tint.to_linear();
+        let color = Color::LinearRgba(LinearRgba::new(
+            tint.red * emission.intensity,
+            tint.green * emission.intensity,
+            tint.blue * emission.intensity,
+            tint.alpha,
+        ));
+        let proxy = (
+            GiEmissionProxy,
+            RenderLayers::layer(GI_EMISSION_LAYER),
+            Transform::default(),
+        );
+
+        if let Some(sprite) = sprite {
+            commands.entity(entity).with_child((
+                Sprite {
+                    image: emission.texture.clone().unwrap_or(sprite.image.clone()),
+                    color,
+                    ..sprite.clone()
+                },
+                proxy,
+            ));
+        } else if let Some(mesh) = mesh {
+            commands.entity(entity).with_child((
+                mesh.clone(),
+                MeshMaterial2d(materials.add(ColorMaterial {
+                    color,
+                    texture: emission.texture.clone(),
+                    ..Default::default()
+                })),
+                proxy,
+            ));
+        }
+    }
+}
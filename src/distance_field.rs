@@ -0,0 +1,352 @@
+use bevy::{
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{
+            binding_types::{texture_2d, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::{CachedTexture, GpuImage, TextureCache},
+    },
+};
+
+use crate::{CanvasImages, JFA_SHADER_ASSET_PATH, PostProcessSettings, scene::SceneTextures};
+
+// Seeds are stored as pixel coordinates, which need more precision than a half float has on large windows.
+const JFA_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rg32Float;
+
+/// Format of the distance field. Distances are stored in pixels divided by the longest side of the canvas.
+pub(crate) const DISTANCE_FIELD_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// The unsigned distance from every pixel to the nearest solid or translucent pixel of the scene.
+/// Rebuilt every frame by the JFA passes of the camera that paints, and shared by every GI camera.
+/// Kept in the main world so it can be displayed for debugging.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct DistanceFieldImage {
+    pub image: Handle<Image>,
+}
+
+#[derive(Clone, Copy, ShaderType)]
+struct JfaUniform {
+    resolution: Vec2,
+    step_size: u32,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct JfaUniforms {
+    buffer: DynamicUniformBuffer<JfaUniform>,
+    /// The first offset is used by the seed and distance passes, the rest are the jump passes in order.
+    offsets: Vec<u32>,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct JfaTextures {
+    ping_pong: Option<[CachedTexture; 2]>,
+}
+
+pub(crate) fn prepare_jfa(
+    canvas_images: Option<Res<CanvasImages>>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut texture_cache: ResMut<TextureCache>,
+    mut uniforms: ResMut<JfaUniforms>,
+    mut textures: ResMut<JfaTextures>,
+) {
+    uniforms.buffer.clear();
+    uniforms.offsets.clear();
+    textures.ping_pong = None;
+
+    let Some(canvas) = canvas_images.and_then(|images| gpu_images.get(&images.target().albedo))
+    else {
+        return;
+    };
+    let width = canvas.texture.width();
+    let height = canvas.texture.height();
+    let resolution = Vec2::new(width as f32, height as f32);
+
+    let offset = uniforms.buffer.push(&JfaUniform {
+        resolution,
+        step_size: 0,
+    });
+    uniforms.offsets.push(offset);
+
+    // Jumps start at half the canvas and halve every pass until they reach single pixels.
+    let mut step_size = width.max(height).next_power_of_two() / 2;
+    while step_size > 0 {
+        let offset = uniforms.buffer.push(&JfaUniform {
+            resolution,
+            step_size,
+        });
+        uniforms.offsets.push(offset);
+        step_size /= 2;
+    }
+    uniforms.buffer.write_buffer(&render_device, &render_queue);
+
+    let descriptor = TextureDescriptor {
+        label: Some("jfa_texture"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: JFA_TEXTURE_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    };
+    textures.ping_pong = Some([
+        texture_cache.get(&render_device, descriptor.clone()),
+        texture_cache.get(&render_device, descriptor),
+    ]);
+}
+
+/// Writes the coordinates of every solid scene pixel into the first JFA texture.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct JfaSeedLabel;
+
+#[derive(Default)]
+pub(crate) struct JfaSeedNode;
+
+/// Runs the jump flood passes over the seeds, then turns the nearest seeds into the distance field.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct JfaLabel;
+
+#[derive(Default)]
+pub(crate) struct JfaNode;
+
+impl ViewNode for JfaSeedNode {
+    // The distance field is built from the shared scene, once for every GI camera
+    type ViewQuery = &'static PostProcessSettings;
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        _settings: QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let jfa_pipeline = world.resource::<JfaPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(jfa_pipeline.seed_pipeline_id)
+        else {
+            return Ok(());
+        };
+
+        let uniforms = world.resource::<JfaUniforms>();
+        let (Some(uniform_binding), Some([seed_texture, _])) = (
+            uniforms.buffer.binding(),
+            &world.resource::<JfaTextures>().ping_pong,
+        ) else {
+            return Ok(());
+        };
+
+        // Only the occluders become seeds, whether they were painted or come from the scene
+        let Some(scene) = world.resource::<SceneTextures>().gbuffer() else {
+            return Ok(());
+        };
+        let src_view = &scene.albedo.default_view;
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "jfa_seed_bind_group",
+            &jfa_pipeline.layout,
+            &BindGroupEntries::sequential((src_view, uniform_binding)),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("jfa_seed_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &seed_texture.default_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[uniforms.offsets[0]]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+impl ViewNode for JfaNode {
+    type ViewQuery = &'static PostProcessSettings;
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        _settings: QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let jfa_pipeline = world.resource::<JfaPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let (Some(jump_pipeline), Some(distance_pipeline)) = (
+            pipeline_cache.get_render_pipeline(jfa_pipeline.jump_pipeline_id),
+            pipeline_cache.get_render_pipeline(jfa_pipeline.distance_pipeline_id),
+        ) else {
+            return Ok(());
+        };
+
+        let uniforms = world.resource::<JfaUniforms>();
+        let (Some(uniform_binding), Some(ping_pong)) = (
+            uniforms.buffer.binding(),
+            &world.resource::<JfaTextures>().ping_pong,
+        ) else {
+            return Ok(());
+        };
+
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let distance_field = world.resource::<DistanceFieldImage>();
+        let Some(distance_field) = gpu_images.get(&distance_field.image) else {
+            return Ok(());
+        };
+
+        // The seed pass wrote to the first texture, so the jumps alternate starting from there.
+        let mut src = 0;
+        for &offset in &uniforms.offsets[1..] {
+            let bind_group = render_context.render_device().create_bind_group(
+                "jfa_bind_group",
+                &jfa_pipeline.layout,
+                &BindGroupEntries::sequential((
+                    &ping_pong[src].default_view,
+                    uniform_binding.clone(),
+                )),
+            );
+
+            let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("jfa_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &ping_pong[1 - src].default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            render_pass.set_render_pipeline(jump_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[offset]);
+            render_pass.draw(0..3, 0..1);
+
+            src = 1 - src;
+        }
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "jfa_distance_bind_group",
+            &jfa_pipeline.layout,
+            &BindGroupEntries::sequential((&ping_pong[src].default_view, uniform_binding)),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("jfa_distance_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &distance_field.texture_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(distance_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[uniforms.offsets[0]]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct JfaPipeline {
+    layout: BindGroupLayout,
+    seed_pipeline_id: CachedRenderPipelineId,
+    jump_pipeline_id: CachedRenderPipelineId,
+    distance_pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for JfaPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "jfa_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // The scene albedo for the seed pass, otherwise the previous JFA texture
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // One uniform per pass, selected with a dynamic offset
+                    uniform_buffer::<JfaUniform>(true),
+                ),
+            ),
+        );
+
+        let shader = world.load_asset(JFA_SHADER_ASSET_PATH);
+
+        let descriptor =
+            |label: &'static str, entry_point: &'static str, format| RenderPipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![layout.clone()],
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: shader.clone(),
+                    shader_defs: vec![],
+                    entry_point: entry_point.into(),
+                    targets: vec![Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                push_constant_ranges: vec![],
+                zero_initialize_workgroup_memory: false,
+            };
+        let seed_descriptor = descriptor("jfa_seed_pipeline", "plant_seeds", JFA_TEXTURE_FORMAT);
+        let jump_descriptor = descriptor("jfa_pipeline", "jump_flood", JFA_TEXTURE_FORMAT);
+        let distance_descriptor = descriptor(
+            "jfa_distance_pipeline",
+            "write_distance",
+            DISTANCE_FIELD_FORMAT,
+        );
+
+        let pipeline_cache = world.resource_mut::<PipelineCache>();
+        let seed_pipeline_id = pipeline_cache.queue_render_pipeline(seed_descriptor);
+        let jump_pipeline_id = pipeline_cache.queue_render_pipeline(jump_descriptor);
+        let distance_pipeline_id = pipeline_cache.queue_render_pipeline(distance_descriptor);
+
+        Self {
+            layout,
+            seed_pipeline_id,
+            jump_pipeline_id,
+            distance_pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+
+use crate::{CascadeSettings, GiConfig, GiOutput, PostProcessSettings, RaymarchSettings};
+
+/// The part of the world the canvas and the GI cover, which every [`GiOutput`] sprite is stretched
+/// over.
+///
+/// By default this is the window, centred on the world's origin. With
+/// [`CascadePlugin::with_world_margin`](crate::CascadePlugin::with_world_margin) it follows the
+/// camera that paints instead, covering its view and a margin around it.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct GiDomain {
+    /// The centre of the canvas in world space.
+    pub center: Vec2,
+    /// The size of the canvas in world units.
+    pub size: Vec2,
+    /// The top left corner of the canvas on a grid of canvas pixels counted from the world's origin,
+    /// with y going down. `None` until the domain follows the camera.
+    origin: Option<IVec2>,
+    /// The size of the canvas in canvas pixels, when the domain follows the camera.
+    pub(crate) texels: Option<UVec2>,
+}
+
+impl GiDomain {
+    pub(crate) fn fixed(size: Vec2) -> Self {
+        Self {
+            center: Vec2::ZERO,
+            size,
+            origin: None,
+            texels: None,
+        }
+    }
+
+    /// Converts a world position to the space of [`PostProcessSettings`] with `resolution` set to
+    /// `size`, which starts at the top left corner of the canvas with y going down.
+    pub fn world_to_canvas(&self, world: Vec2) -> Vec2 {
+        let top_left = self.center + Vec2::new(-0.5, 0.5) * self.size;
+        (world - top_left) * Vec2::new(1.0, -1.0)
+    }
+}
+
+/// Moves the domain along with the camera that paints, in whole steps of the largest top cascade's
+/// probe spacing, so every probe of every GI camera stays in the same place in the world and the
+/// lighting doesn't swim as the camera pans.
+///
+/// When the domain moves, the canvas and the raymarch history move the other way by as many canvas
+/// pixels, so the paint stays where it was in the world. Paint that falls off the edge is lost.
+pub(crate) fn follow_camera(
+    config: Res<GiConfig>,
+    mut domain: ResMut<GiDomain>,
+    mut painter: Query<(&GlobalTransform, &Projection, &mut PostProcessSettings), With<Camera2d>>,
+    mut views: Query<(&CascadeSettings, &mut RaymarchSettings)>,
+    mut outputs: Query<(&mut Transform, &mut Sprite), With<GiOutput>>,
+) {
+    let Some(margin) = config.world_margin else {
+        return;
+    };
+    let Ok((transform, projection, mut brush)) = painter.single_mut() else {
+        return;
+    };
+    let Projection::Orthographic(orthographic) = projection else {
+        return;
+    };
+    let Some(cascade_count) = views
+        .iter()
+        .map(|(cascade_settings, _)| cascade_settings.cascade_count)
+        .max()
+    else {
+        return;
+    };
+
+    // Canvas pixels keep their size in the world, so zooming out covers more of them
+    let texel = 1.0 / config.resolution_scale;
+    let block = 2i32 << (cascade_count.max(1) - 1);
+    let step = texel * block as f32;
+    // Two blocks more than the view and margin need, so the view stays covered between steps
+    let size = (((orthographic.area.size() + 2.0 * margin) / step)
+        .ceil()
+        .as_ivec2()
+        + 2)
+        * block;
+    let camera = transform.translation().truncate() * Vec2::new(1.0, -1.0) / texel;
+    let origin = ((camera - size.as_vec2() * 0.5) / block as f32)
+        .floor()
+        .as_ivec2()
+        * block;
+
+    let scroll = domain
+        .origin
+        .map_or(IVec2::ZERO, |previous| origin - previous);
+    brush.scroll = scroll;
+    for (_, mut raymarch_settings) in &mut views {
+        raymarch_settings.scroll = scroll;
+    }
+
+    domain.origin = Some(origin);
+    domain.texels = Some(size.as_uvec2());
+    domain.size = size.as_vec2() * texel;
+    domain.center = (origin.as_vec2() + size.as_vec2() * 0.5) * texel * Vec2::new(1.0, -1.0);
+
+    for (mut sprite_transform, mut sprite) in &mut outputs {
+        sprite_transform.translation = domain.center.extend(sprite_transform.translation.z);
+        sprite.custom_size = Some(domain.size);
+    }
+}
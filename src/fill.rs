@@ -0,0 +1,269 @@
+use bevy::prelude::*;
+
+use crate::{
+    CanvasTool, PostProcessSettings,
+    canvas_file::{CanvasReadbackPurpose, CanvasTexels, encode_texels, spawn_canvas_readback},
+    layers::{CanvasLayers, LayerId},
+};
+
+/// The layers with a fill that hasn't landed yet, once for every fill.
+#[derive(Resource, Default)]
+pub(crate) struct PendingFills {
+    layers: Vec<LayerId>,
+}
+
+impl PendingFills {
+    pub(crate) fn contains(&self, layer: LayerId) -> bool {
+        self.layers.contains(&layer)
+    }
+
+    /// Lets `layer` be painted on again once a fill on it has landed, or failed to.
+    pub(crate) fn finish(&mut self, layer: LayerId) {
+        if let Some(index) = self.layers.iter().position(|&pending| pending == layer) {
+            self.layers.swap_remove(index);
+        }
+    }
+}
+
+/// Reads the active layer back when a stroke starts with [`CanvasTool::Fill`], to be filled on the
+/// CPU.
+pub(crate) fn start_fill(
+    mut commands: Commands,
+    mut was_drawing: Local<bool>,
+    mut fills: ResMut<PendingFills>,
+    brushes: Query<&PostProcessSettings>,
+    layers: Res<CanvasLayers>,
+    images: Res<Assets<Image>>,
+) {
+    let Ok(brush) = brushes.single() else {
+        return;
+    };
+    let drawing = brush.drawing != 0;
+    let stroke_started = drawing && !*was_drawing;
+    *was_drawing = drawing;
+    if !stroke_started || brush.tool() != CanvasTool::Fill {
+        return;
+    }
+
+    let Some(canvas) = images.get(&layers.target(layers.active()).albedo) else {
+        return;
+    };
+    // The stroke is in the brush's resolution, which needn't match the canvas
+    let seed = (brush.to / brush.resolution.max(Vec2::ONE) * canvas.size().as_vec2())
+        .as_uvec2()
+        .min(canvas.size().saturating_sub(UVec2::ONE));
+    // Premultiplied like the paint the brush leaves
+    let alpha = brush.paint_alpha();
+    let layer = layers.active().id();
+    if spawn_canvas_readback(
+        &mut commands,
+        &layers,
+        layers.active(),
+        &images,
+        CanvasReadbackPurpose::Fill {
+            layer,
+            seed,
+            emission: (brush.color * brush.emission * alpha).extend(alpha),
+            albedo: (brush.color * alpha).extend(alpha),
+        },
+    ) {
+        fills.layers.push(layer);
+    }
+}
+
+/// Keeps the brush off a layer until the fills on it have landed. A fill writes back the whole layer
+/// as it was read, which would undo anything painted on it in the meantime.
+pub(crate) fn hold_strokes_for_fills(
+    fills: Res<PendingFills>,
+    layers: Res<CanvasLayers>,
+    mut brushes: Query<&mut PostProcessSettings>,
+) {
+    if !fills.contains(layers.active().id()) {
+        return;
+    }
+    for mut brush in &mut brushes {
+        brush.drawing = 0;
+        brush.commit_shape = 0;
+    }
+}
+
+/// Paints `emission` and `albedo` over every pixel connected to `seed` that has exactly the same
+/// paint as it, in both channels.
+pub(crate) fn flood_fill(
+    emission: &mut CanvasTexels,
+    albedo: &mut CanvasTexels,
+    seed: UVec2,
+    fill_emission: Vec4,
+    fill_albedo: Vec4,
+) -> Result<(), String> {
+    let size = albedo.size;
+    if emission.size != size {
+        return Err("the canvas channels don't match in size".to_string());
+    }
+    if seed.x >= size.x || seed.y >= size.y {
+        return Err(format!(
+            "the fill starts at {seed}, outside the {size} canvas"
+        ));
+    }
+    let pixel_count = (size.x * size.y) as usize;
+    let emission_stride = emission.data.len() / pixel_count;
+    let albedo_stride = albedo.data.len() / pixel_count;
+
+    let new_emission = encode_texels(&fill_emission.to_array(), emission.format)?;
+    let new_albedo = encode_texels(&fill_albedo.to_array(), albedo.format)?;
+    let seed_index = (seed.y * size.x + seed.x) as usize;
+    let old_emission = emission.data[seed_index * emission_stride..][..emission_stride].to_vec();
+    let old_albedo = albedo.data[seed_index * albedo_stride..][..albedo_stride].to_vec();
+    // Filling a region with its own paint would never stop finding pixels to fill
+    if old_emission == new_emission && old_albedo == new_albedo {
+        return Ok(());
+    }
+
+    let mut stack = vec![seed];
+    while let Some(pixel) = stack.pop() {
+        let index = (pixel.y * size.x + pixel.x) as usize;
+        let emission_texel = &mut emission.data[index * emission_stride..][..emission_stride];
+        let albedo_texel = &mut albedo.data[index * albedo_stride..][..albedo_stride];
+        if *emission_texel != old_emission[..] || *albedo_texel != old_albedo[..] {
+            continue;
+        }
+        emission_texel.copy_from_slice(&new_emission);
+        albedo_texel.copy_from_slice(&new_albedo);
+
+        if pixel.x > 0 {
+            stack.push(pixel - UVec2::X);
+        }
+        if pixel.y > 0 {
+            stack.push(pixel - UVec2::Y);
+        }
+        if pixel.x + 1 < size.x {
+            stack.push(pixel + UVec2::X);
+        }
+        if pixel.y + 1 < size.y {
+            stack.push(pixel + UVec2::Y);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::TextureFormat;
+
+    use super::*;
+
+    const FILL: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+
+    /// Both channels of a canvas painted white wherever `rows` has a `#`.
+    fn canvas(rows: &[&str]) -> (CanvasTexels, CanvasTexels) {
+        let data = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|pixel| if pixel == '#' { [255; 4] } else { [0; 4] })
+            .collect();
+        let texels = CanvasTexels {
+            size: UVec2::new(rows[0].len() as u32, rows.len() as u32),
+            format: TextureFormat::Rgba8Unorm,
+            data,
+        };
+        (texels.clone(), texels)
+    }
+
+    /// Draws `texels` the way `canvas` takes them, with an `o` wherever the fill landed.
+    fn rows(texels: &CanvasTexels) -> Vec<String> {
+        texels
+            .data
+            .chunks(4)
+            .map(|texel| match texel {
+                [255, 255, 255, 255] => '#',
+                [255, 0, 0, 255] => 'o',
+                _ => '.',
+            })
+            .collect::<Vec<_>>()
+            .chunks(texels.size.x as usize)
+            .map(|row| row.iter().collect())
+            .collect()
+    }
+
+    #[rustfmt::skip]
+    const BOX: [&str; 4] = [
+        "#####.",
+        "#...#.",
+        "#...#.",
+        "#####.",
+    ];
+
+    #[rustfmt::skip]
+    const FILLED_BOX: [&str; 4] = [
+        "#####.",
+        "#ooo#.",
+        "#ooo#.",
+        "#####.",
+    ];
+
+    #[rustfmt::skip]
+    const ISLAND: [&str; 4] = [
+        "......",
+        ".##...",
+        ".##...",
+        "......",
+    ];
+
+    #[rustfmt::skip]
+    const FILLED_AROUND_ISLAND: [&str; 4] = [
+        "oooooo",
+        "o##ooo",
+        "o##ooo",
+        "oooooo",
+    ];
+
+    #[test]
+    fn fills_an_enclosed_region() {
+        let (mut emission, mut albedo) = canvas(&BOX);
+        flood_fill(&mut emission, &mut albedo, UVec2::new(2, 1), FILL, FILL).unwrap();
+        assert_eq!(rows(&albedo), FILLED_BOX);
+        assert_eq!(rows(&emission), FILLED_BOX);
+    }
+
+    #[test]
+    fn fills_up_to_the_edges() {
+        let (mut emission, mut albedo) = canvas(&ISLAND);
+        flood_fill(&mut emission, &mut albedo, UVec2::ZERO, FILL, FILL).unwrap();
+        assert_eq!(rows(&albedo), FILLED_AROUND_ISLAND);
+    }
+
+    #[test]
+    fn rejects_a_seed_outside_the_canvas() {
+        let (mut emission, mut albedo) = canvas(&["...", "..."]);
+        let untouched = albedo.data.clone();
+        for seed in [UVec2::new(3, 0), UVec2::new(0, 2)] {
+            assert!(flood_fill(&mut emission, &mut albedo, seed, FILL, FILL).is_err());
+        }
+        assert_eq!(albedo.data, untouched);
+    }
+
+    #[test]
+    fn filling_with_the_paint_already_there_changes_nothing() {
+        let (mut emission, mut albedo) = canvas(&["#..", "..."]);
+        let untouched = albedo.data.clone();
+        // Empty paint over empty pixels, and white paint over the white pixel
+        flood_fill(
+            &mut emission,
+            &mut albedo,
+            UVec2::new(1, 1),
+            Vec4::ZERO,
+            Vec4::ZERO,
+        )
+        .unwrap();
+        flood_fill(
+            &mut emission,
+            &mut albedo,
+            UVec2::ZERO,
+            Vec4::ONE,
+            Vec4::ONE,
+        )
+        .unwrap();
+        assert_eq!(albedo.data, untouched);
+        assert_eq!(emission.data, untouched);
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    CanvasTool, PostProcessSettings,
+    canvas_file::{CanvasLoaded, CanvasTexels, restore_texels, spawn_canvas_snapshot},
+    fill::PendingFills,
+    layers::{CanvasLayer, CanvasLayers, LayerId},
+};
+
+/// Takes back the last finished stroke, or loaded canvas file, on whichever layer it was made.
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct UndoCanvas;
+
+/// Puts back the last change taken back with [`UndoCanvas`].
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct RedoCanvas;
+
+/// A copy of one layer's paint that undo can put back.
+pub(crate) struct LayerSnapshot {
+    pub(crate) layer: LayerId,
+    pub(crate) emission: CanvasTexels,
+    pub(crate) albedo: CanvasTexels,
+}
+
+impl LayerSnapshot {
+    fn memory_used(&self) -> usize {
+        self.emission.data.len() + self.albedo.data.len()
+    }
+}
+
+/// A state of the canvas that undo can return to, holding the layers the change to it was made to.
+struct CanvasState {
+    layers: Vec<LayerSnapshot>,
+}
+
+impl CanvasState {
+    fn memory_used(&self) -> usize {
+        self.layers.iter().map(LayerSnapshot::memory_used).sum()
+    }
+
+    fn get(&self, layer: LayerId) -> Option<&LayerSnapshot> {
+        self.layers.iter().find(|snapshot| snapshot.layer == layer)
+    }
+}
+
+/// The undo history of the canvas.
+///
+/// The active layer is read back from the GPU every time a stroke ends, and every layer when a
+/// canvas file is loaded. The oldest snapshots are forgotten once they take up more than the budget
+/// set with [`CascadePlugin::with_undo_budget`](crate::CascadePlugin::with_undo_budget). A change
+/// can only be undone while the states its layers were in before it are still kept, and changes to
+/// layers that have since been removed are forgotten.
+#[derive(Resource)]
+pub struct CanvasHistory {
+    /// `None` is the empty canvas the app starts with, where every layer is empty.
+    states: VecDeque<Option<CanvasState>>,
+    current: usize,
+    budget: usize,
+    /// Snapshots that haven't been read back yet. Undo waits for them, so they land in order.
+    pending: usize,
+}
+
+impl CanvasHistory {
+    pub(crate) fn new(budget: usize) -> Self {
+        Self {
+            states: VecDeque::from([None]),
+            current: 0,
+            budget,
+            pending: 0,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo_step().is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.pending == 0 && self.current + 1 < self.states.len()
+    }
+
+    /// The memory taken up by the snapshots, in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.states
+            .iter()
+            .flatten()
+            .map(CanvasState::memory_used)
+            .sum()
+    }
+
+    /// The most memory the snapshots may take up, in bytes.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// The layers the current change was made to, each with the index of the state undoing it puts
+    /// that layer back in.
+    fn undo_step(&self) -> Option<Vec<(LayerId, Option<usize>)>> {
+        if self.pending > 0 || self.current == 0 {
+            return None;
+        }
+        // Once the empty canvas has been forgotten, a layer without an earlier state can't be told
+        // apart from one whose earlier state went over budget
+        let forgotten = self.states.front().is_some_and(Option::is_some);
+        self.states[self.current]
+            .as_ref()?
+            .layers
+            .iter()
+            .map(|snapshot| {
+                let layer = snapshot.layer;
+                let before = (0..self.current).rev().find(|&index| {
+                    self.states[index]
+                        .as_ref()
+                        .is_some_and(|state| state.get(layer).is_some())
+                });
+                match before {
+                    Some(index) => Some((layer, Some(index))),
+                    None if forgotten => None,
+                    None => Some((layer, None)),
+                }
+            })
+            .collect()
+    }
+
+    /// Steps back over the current change, returning the layers it was made to and the states those
+    /// layers go back to, `None` for an empty layer.
+    fn undo(&mut self) -> Option<Vec<(LayerId, Option<&LayerSnapshot>)>> {
+        let step = self.undo_step()?;
+        self.current -= 1;
+        Some(
+            step.into_iter()
+                .map(|(layer, before)| {
+                    let snapshot = before.and_then(|index| self.states[index].as_ref()?.get(layer));
+                    (layer, snapshot)
+                })
+                .collect(),
+        )
+    }
+
+    /// Steps forward over the next change that was undone, returning the layers it puts back.
+    fn redo(&mut self) -> Option<&[LayerSnapshot]> {
+        if !self.can_redo() {
+            return None;
+        }
+        self.current += 1;
+        self.states[self.current]
+            .as_ref()
+            .map(|state| state.layers.as_slice())
+    }
+
+    /// Takes in the snapshots read back by `snapshot_canvas`.
+    pub(crate) fn push(&mut self, layers: Vec<LayerSnapshot>) {
+        self.pending = self.pending.saturating_sub(1);
+        self.record(layers);
+    }
+
+    /// Makes a state of `layers` the current state, throwing away anything that could have been
+    /// redone.
+    pub(crate) fn record(&mut self, layers: Vec<LayerSnapshot>) {
+        self.states.truncate(self.current + 1);
+        self.states.push_back(Some(CanvasState { layers }));
+        self.current = self.states.len() - 1;
+
+        // The current state is kept even if it's over budget on its own
+        while self.memory_used() > self.budget && self.current > 0 {
+            self.states.pop_front();
+            self.current -= 1;
+        }
+    }
+
+    pub(crate) fn snapshot_failed(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    /// Forgets the snapshots of layers that no longer exist, and the states left with none, so undo
+    /// doesn't spend a step on a change it has nothing to put back for.
+    fn forget_removed_layers(&mut self, exists: impl Fn(LayerId) -> bool) {
+        let current = self.current;
+        let mut index = 0;
+        self.states.retain_mut(|state| {
+            let keep = state.as_mut().is_none_or(|state| {
+                state.layers.retain(|snapshot| exists(snapshot.layer));
+                !state.layers.is_empty()
+            });
+            // Forgetting the current state leaves the canvas in the one before it
+            if !keep && index <= current {
+                self.current -= 1;
+            }
+            index += 1;
+            keep
+        });
+    }
+}
+
+/// What [`snapshot_canvas`] tells the end of a stroke from.
+#[derive(SystemParam)]
+pub(crate) struct Strokes<'w, 's> {
+    was_drawing: Local<'s, bool>,
+    brushes: Query<'w, 's, &'static PostProcessSettings>,
+    fills: Res<'w, PendingFills>,
+}
+
+/// Snapshots the active layer once a stroke has ended, or every layer once a canvas file has been
+/// loaded.
+pub(crate) fn snapshot_canvas(
+    mut commands: Commands,
+    mut history: ResMut<CanvasHistory>,
+    mut loaded: EventReader<CanvasLoaded>,
+    strokes: Strokes,
+    layers: Res<CanvasLayers>,
+    images: Res<Assets<Image>>,
+) {
+    let Strokes {
+        mut was_drawing,
+        brushes,
+        fills,
+    } = strokes;
+    let drawing = brushes.iter().any(|brush| brush.drawing != 0);
+    // Fills record themselves once they've been applied, and strokes held back by one paint nothing
+    let stroke_ended = *was_drawing
+        && !drawing
+        && brushes.iter().all(|brush| brush.tool() != CanvasTool::Fill)
+        && !fills.contains(layers.active().id());
+    *was_drawing = drawing;
+    let canvas_loaded = loaded.read().any(|loaded| loaded.result.is_ok());
+
+    if history.budget == 0 || !(stroke_ended || canvas_loaded) {
+        return;
+    }
+    // The side the canvas pass writes on the frame the stroke ends is complete, with any shape the
+    // stroke dragged out painted into it
+    let snapshot_layers: Vec<&CanvasLayer> = if canvas_loaded {
+        layers.iter().collect()
+    } else {
+        vec![layers.active()]
+    };
+    if spawn_canvas_snapshot(&mut commands, &layers, &snapshot_layers, &images) {
+        history.pending += 1;
+    }
+}
+
+pub(crate) fn undo_redo_canvas(
+    mut undo: EventReader<UndoCanvas>,
+    mut redo: EventReader<RedoCanvas>,
+    mut history: ResMut<CanvasHistory>,
+    layers: Res<CanvasLayers>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if layers.is_changed() {
+        history.forget_removed_layers(|layer| layers.get(layer).is_some());
+    }
+    for _ in undo.read() {
+        for (layer, snapshot) in history.undo().into_iter().flatten() {
+            restore_layer(&layers, &mut images, layer, snapshot);
+        }
+    }
+    for _ in redo.read() {
+        for snapshot in history.redo().into_iter().flatten() {
+            restore_layer(&layers, &mut images, snapshot.layer, Some(snapshot));
+        }
+    }
+}
+
+/// Puts `layer` back in `snapshot`, or clears it if there's no snapshot.
+fn restore_layer(
+    layers: &CanvasLayers,
+    images: &mut Assets<Image>,
+    layer: LayerId,
+    snapshot: Option<&LayerSnapshot>,
+) {
+    // A removed layer has nothing left to restore
+    let Some(layer) = layers.get(layer) else {
+        return;
+    };
+    // Both sides of the ping pong get the state, like a loaded canvas
+    for side in layer.sides() {
+        let result = restore_texels(
+            images,
+            &side.emission,
+            snapshot.map(|snapshot| &snapshot.emission),
+        )
+        .and_then(|()| {
+            restore_texels(
+                images,
+                &side.albedo,
+                snapshot.map(|snapshot| &snapshot.albedo),
+            )
+        });
+        if let Err(error) = result {
+            error!("Couldn't restore the canvas: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::TextureFormat;
+
+    use super::*;
+
+    const LAYER: LayerId = LayerId(0);
+
+    /// A snapshot of 8 bytes whose texels all hold `value`, so states can be told apart.
+    fn snapshot(layer: LayerId, value: u8) -> LayerSnapshot {
+        let texels = CanvasTexels {
+            size: UVec2::ONE,
+            format: TextureFormat::Rgba8Unorm,
+            data: vec![value; 4],
+        };
+        LayerSnapshot {
+            layer,
+            emission: texels.clone(),
+            albedo: texels,
+        }
+    }
+
+    fn record(history: &mut CanvasHistory, value: u8) {
+        history.record(vec![snapshot(LAYER, value)]);
+    }
+
+    fn value(snapshot: Option<&LayerSnapshot>) -> Option<u8> {
+        snapshot.map(|snapshot| snapshot.emission.data[0])
+    }
+
+    /// The layers an undo puts back, with the value each goes back to.
+    fn undo(history: &mut CanvasHistory) -> Option<Vec<(LayerId, Option<u8>)>> {
+        history.undo().map(|step| {
+            step.into_iter()
+                .map(|(layer, snapshot)| (layer, value(snapshot)))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn recording_past_the_budget_forgets_the_oldest_states() {
+        let mut history = CanvasHistory::new(16);
+        for state in 1..=3 {
+            record(&mut history, state);
+        }
+
+        assert_eq!(history.memory_used(), 16);
+        assert_eq!(history.states.len(), 2);
+        assert_eq!(
+            history.states[0].as_ref().unwrap().layers[0].emission.data[0],
+            2
+        );
+        assert_eq!(
+            history.states[history.current].as_ref().unwrap().layers[0]
+                .emission
+                .data[0],
+            3
+        );
+        // The empty canvas and the first state are gone, so only one change can be undone
+        assert_eq!(undo(&mut history), Some(vec![(LAYER, Some(2))]));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn recording_after_an_undo_drops_what_could_be_redone() {
+        let mut history = CanvasHistory::new(usize::MAX);
+        record(&mut history, 1);
+        record(&mut history, 2);
+        assert_eq!(undo(&mut history), Some(vec![(LAYER, Some(1))]));
+        assert!(history.can_redo());
+
+        record(&mut history, 3);
+        assert!(!history.can_redo());
+        assert!(history.redo().is_none());
+        assert_eq!(history.states.len(), 3);
+        // Undoing the new state skips straight past the one it replaced
+        assert_eq!(undo(&mut history), Some(vec![(LAYER, Some(1))]));
+    }
+
+    #[test]
+    fn undoing_the_oldest_state_does_nothing() {
+        let mut history = CanvasHistory::new(usize::MAX);
+        assert!(history.undo().is_none());
+
+        record(&mut history, 1);
+        // Back to the empty canvas the app starts with
+        assert_eq!(undo(&mut history), Some(vec![(LAYER, None)]));
+        assert!(history.undo().is_none());
+        assert_eq!(history.current, 0);
+        assert_eq!(value(history.redo().unwrap().first()), Some(1));
+    }
+
+    #[test]
+    fn strokes_after_a_load_undo_to_the_loaded_layers() {
+        let (bottom, top) = (LayerId(0), LayerId(1));
+        let mut history = CanvasHistory::new(usize::MAX);
+        record(&mut history, 1);
+        // A canvas file with both layers is loaded, then painted over on the top layer
+        history.record(vec![snapshot(bottom, 2), snapshot(top, 3)]);
+        history.record(vec![snapshot(top, 4)]);
+
+        assert_eq!(undo(&mut history), Some(vec![(top, Some(3))]));
+        // The load is one step, taking each layer back to where it was before
+        assert_eq!(
+            undo(&mut history),
+            Some(vec![(bottom, Some(1)), (top, None)])
+        );
+        let redone: Vec<_> = history.redo().unwrap().iter().map(|s| s.layer).collect();
+        assert_eq!(redone, [bottom, top]);
+    }
+
+    #[test]
+    fn changes_to_removed_layers_are_forgotten() {
+        let (bottom, top) = (LayerId(0), LayerId(1));
+        let mut history = CanvasHistory::new(usize::MAX);
+        record(&mut history, 1);
+        history.record(vec![snapshot(top, 2)]);
+        history.record(vec![snapshot(bottom, 3), snapshot(top, 4)]);
+
+        history.forget_removed_layers(|layer| layer != top);
+        assert_eq!(history.states.len(), 3);
+        assert_eq!(history.current, 2);
+        // Undo goes straight to the bottom layer's state, without a step for the top layer
+        assert_eq!(undo(&mut history), Some(vec![(bottom, Some(1))]));
+        assert_eq!(undo(&mut history), Some(vec![(bottom, None)]));
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,520 @@
+use bevy::{
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{binding_types::texture_2d, *},
+        renderer::{RenderContext, RenderDevice},
+        texture::GpuImage,
+    },
+};
+
+use crate::{
+    COMPOSITE_SHADER_ASSET_PATH, CanvasGBuffer, CanvasImages, GiTextureFormats,
+    PostProcessSettings, reformat_empty_image,
+};
+
+/// Blends the visible canvas layers into the canvas the GI passes read.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct CompositePassLabel;
+
+#[derive(Default)]
+pub(crate) struct CompositeNode;
+
+/// Names a layer for as long as it exists, wherever it's moved in the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayerId(pub(crate) u32);
+
+/// How a layer is blended into the layers below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LayerBlend {
+    /// Paint covers what's below it.
+    #[default]
+    Normal,
+    /// Paint adds its colour and light to what's below it.
+    Add,
+    /// Paint tints what's below it.
+    Multiply,
+}
+
+impl LayerBlend {
+    pub const ALL: [Self; 3] = [Self::Normal, Self::Add, Self::Multiply];
+}
+
+/// Which channels of the canvas a layer's paint goes into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LayerRole {
+    /// Paint is solid and gives off light as it was painted.
+    #[default]
+    Emitter,
+    /// Paint is solid and gives off no light, whatever its emission, so it hides lights below it.
+    Occluder,
+    /// Paint only recolours the surfaces below it, without blocking or giving off light.
+    Albedo,
+}
+
+impl LayerRole {
+    pub const ALL: [Self; 3] = [Self::Emitter, Self::Occluder, Self::Albedo];
+}
+
+/// One layer of the canvas, painted separately from the others.
+#[derive(Clone)]
+pub struct CanvasLayer {
+    id: LayerId,
+    pub name: String,
+    /// Hidden layers keep their paint but leave the canvas as if they weren't there.
+    pub visible: bool,
+    pub blend: LayerBlend,
+    pub role: LayerRole,
+    front: CanvasGBuffer,
+    back: CanvasGBuffer,
+}
+
+impl CanvasLayer {
+    pub fn id(&self) -> LayerId {
+        self.id
+    }
+
+    /// Both sides of the layer's ping pong, which a restored layer has to be written to.
+    pub(crate) fn sides(&self) -> [&CanvasGBuffer; 2] {
+        [&self.front, &self.back]
+    }
+}
+
+/// The stack of layers the canvas is painted in, from the bottom up.
+///
+/// Strokes, fills, loaded canvases and undo all go to the active layer. There is always at least one
+/// layer.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct CanvasLayers {
+    layers: Vec<CanvasLayer>,
+    active: usize,
+    next_id: u32,
+    /// Flipped along with [`CanvasImages::target_front`].
+    pub(crate) target_front: bool,
+}
+
+impl CanvasLayers {
+    /// A stack of a single layer, painted into `front` and `back`.
+    pub(crate) fn new(front: CanvasGBuffer, back: CanvasGBuffer) -> Self {
+        Self {
+            layers: vec![CanvasLayer {
+                id: LayerId(0),
+                name: "Layer 1".to_string(),
+                visible: true,
+                blend: LayerBlend::default(),
+                role: LayerRole::default(),
+                front,
+                back,
+            }],
+            active: 0,
+            next_id: 1,
+            target_front: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Always false, the last layer can't be removed.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// The layers from the bottom up.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &CanvasLayer> + ExactSizeIterator {
+        self.layers.iter()
+    }
+
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut CanvasLayer> + ExactSizeIterator {
+        self.layers.iter_mut()
+    }
+
+    pub fn get(&self, id: LayerId) -> Option<&CanvasLayer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
+
+    /// Where the layer is in the stack, 0 being the bottom.
+    pub fn index_of(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+
+    pub fn active(&self) -> &CanvasLayer {
+        &self.layers[self.active]
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn set_active(&mut self, index: usize) {
+        if index < self.layers.len() {
+            self.active = index;
+        }
+    }
+
+    /// Adds an empty layer right above the active one, and makes it the active layer. Returns `None`
+    /// if the canvas images aren't there to copy the size and formats of.
+    pub fn add(&mut self, name: impl Into<String>, images: &mut Assets<Image>) -> Option<LayerId> {
+        let template = self.active();
+        // Each side copies its own size, in case only one of them has been resized yet
+        let front = empty_gbuffer_like(images, &template.front)?;
+        let back = empty_gbuffer_like(images, &template.back)?;
+        let id = LayerId(self.next_id);
+        self.next_id += 1;
+        self.active += 1;
+        self.layers.insert(
+            self.active,
+            CanvasLayer {
+                id,
+                name: name.into(),
+                visible: true,
+                blend: LayerBlend::default(),
+                role: LayerRole::default(),
+                front,
+                back,
+            },
+        );
+        Some(id)
+    }
+
+    /// Removes the layer at `index` along with its paint. Returns false for the last layer left.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if self.layers.len() <= 1 || index >= self.layers.len() {
+            return false;
+        }
+        self.layers.remove(index);
+        if self.active > index || self.active == self.layers.len() {
+            self.active -= 1;
+        }
+        true
+    }
+
+    /// Moves the layer at `from` to `to` in the stack. The active layer stays active.
+    pub fn move_layer(&mut self, from: usize, to: usize) {
+        if from >= self.layers.len() || to >= self.layers.len() {
+            return;
+        }
+        let active = self.active().id;
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+        self.active = self.index_of(active).unwrap_or_default();
+    }
+
+    /// The side of a layer's ping pong the canvas pass reads from this frame.
+    pub fn source<'a>(&self, layer: &'a CanvasLayer) -> &'a CanvasGBuffer {
+        if self.target_front {
+            &layer.back
+        } else {
+            &layer.front
+        }
+    }
+
+    /// The side of a layer's ping pong the canvas pass writes to this frame, which the composite
+    /// pass reads.
+    pub fn target<'a>(&self, layer: &'a CanvasLayer) -> &'a CanvasGBuffer {
+        if self.target_front {
+            &layer.front
+        } else {
+            &layer.back
+        }
+    }
+}
+
+fn empty_gbuffer_like(
+    images: &mut Assets<Image>,
+    gbuffer: &CanvasGBuffer,
+) -> Option<CanvasGBuffer> {
+    let mut empty_like = |handle: &Handle<Image>| {
+        let image = images.get(handle)?;
+        let empty = reformat_empty_image(image, image.texture_descriptor.format);
+        Some(images.add(empty))
+    };
+    Some(CanvasGBuffer {
+        emission: empty_like(&gbuffer.emission)?,
+        albedo: empty_like(&gbuffer.albedo)?,
+    })
+}
+
+impl ViewNode for CompositeNode {
+    // Like the canvas pass, this only runs for the camera that paints
+    type ViewQuery = &'static PostProcessSettings;
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        _settings: QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let composite_pipeline = world.resource::<CompositePipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let layers = world.resource::<CanvasLayers>();
+        let canvas = world.resource::<CanvasImages>().target();
+        let (Some(canvas_emission), Some(canvas_albedo)) = (
+            gpu_images.get(&canvas.emission),
+            gpu_images.get(&canvas.albedo),
+        ) else {
+            return Ok(());
+        };
+
+        // A layer whose pipeline is still compiling is left out rather than holding up the others
+        let draws: Vec<_> = layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .filter_map(|layer| {
+                let pipeline = pipeline_cache
+                    .get_render_pipeline(composite_pipeline.pipeline_id(layer.blend, layer.role))?;
+                // The canvas pass has just written to this side
+                let painted = layers.target(layer);
+                let bind_group = render_context.render_device().create_bind_group(
+                    "composite_bind_group",
+                    &composite_pipeline.layout,
+                    &BindGroupEntries::sequential((
+                        &gpu_images.get(&painted.emission)?.texture_view,
+                        &gpu_images.get(&painted.albedo)?.texture_view,
+                    )),
+                );
+                Some((pipeline, bind_group))
+            })
+            .collect();
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("composite_pass"),
+            color_attachments: &[
+                color_attachment(canvas_emission),
+                color_attachment(canvas_albedo),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        // Bottom layer first, so each layer blends over the ones below it
+        for (pipeline, bind_group) in &draws {
+            render_pass.set_render_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        Ok(())
+    }
+}
+
+/// One pipeline for every combination of [`LayerBlend`] and [`LayerRole`], since blending is fixed
+/// function state.
+#[derive(Resource)]
+pub(crate) struct CompositePipeline {
+    layout: BindGroupLayout,
+    pipeline_ids: [[CachedRenderPipelineId; 3]; 3],
+}
+
+impl CompositePipeline {
+    fn pipeline_id(&self, blend: LayerBlend, role: LayerRole) -> CachedRenderPipelineId {
+        self.pipeline_ids[blend as usize][role as usize]
+    }
+}
+
+fn color_attachment<'a>(image: &'a GpuImage) -> Option<RenderPassColorAttachment<'a>> {
+    Some(RenderPassColorAttachment {
+        view: &image.texture_view,
+        resolve_target: None,
+        ops: Operations {
+            // The canvas is built up from nothing every frame
+            load: LoadOp::Clear(LinearRgba::NONE.into()),
+            store: StoreOp::Store,
+        },
+    })
+}
+
+/// Blending for premultiplied paint, which is how the canvas pass leaves it.
+fn blend_state(blend: LayerBlend) -> BlendState {
+    match blend {
+        LayerBlend::Normal => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        LayerBlend::Add => BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            // Adding occluder masks would make faint paint solid where it overlaps
+            alpha: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Max,
+            },
+        },
+        // The shader turns the paint into a factor, which is 1.0 where there's none
+        LayerBlend::Multiply => BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::Dst,
+                dst_factor: BlendFactor::Zero,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        },
+    }
+}
+
+/// Blending for an albedo layer, which recolours the surfaces below it without changing how much of
+/// the pixel they cover. The albedo stays premultiplied by that coverage, so the new colour is
+/// weighted by the destination alpha rather than written as painted.
+fn albedo_blend_state(blend: LayerBlend) -> BlendState {
+    let color = match blend {
+        LayerBlend::Normal => BlendComponent {
+            src_factor: BlendFactor::DstAlpha,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        },
+        LayerBlend::Add => BlendComponent {
+            src_factor: BlendFactor::DstAlpha,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        },
+        // Tinting by a factor of at most 1.0 already keeps the colour within the coverage
+        LayerBlend::Multiply => blend_state(blend).color,
+    };
+    BlendState {
+        color,
+        alpha: BlendComponent {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        },
+    }
+}
+
+impl FromWorld for CompositePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let formats = *world.resource::<GiTextureFormats>();
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "composite_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // The layer's G-buffer, copied pixel for pixel
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+            ),
+        );
+
+        let shader = world.load_asset(COMPOSITE_SHADER_ASSET_PATH);
+        let pipeline_ids = LayerBlend::ALL.map(|blend| {
+            LayerRole::ALL.map(|role| {
+                let mut shader_defs: Vec<ShaderDefVal> = Vec::new();
+                if role == LayerRole::Occluder {
+                    shader_defs.push("ROLE_OCCLUDER".into());
+                }
+                if blend == LayerBlend::Multiply {
+                    shader_defs.push("BLEND_MULTIPLY".into());
+                }
+                // Albedo layers leave the light to the layers below
+                let emission_writes = match role {
+                    LayerRole::Albedo => ColorWrites::empty(),
+                    _ => ColorWrites::ALL,
+                };
+
+                world.resource_mut::<PipelineCache>().queue_render_pipeline(
+                    RenderPipelineDescriptor {
+                        label: Some("composite_pipeline".into()),
+                        layout: vec![layout.clone()],
+                        vertex: fullscreen_shader_vertex_state(),
+                        fragment: Some(FragmentState {
+                            shader: shader.clone(),
+                            shader_defs,
+                            entry_point: "fragment".into(),
+                            // Same channels as the canvas
+                            targets: vec![
+                                Some(ColorTargetState {
+                                    format: formats.emission,
+                                    blend: Some(blend_state(blend)),
+                                    write_mask: emission_writes,
+                                }),
+                                Some(ColorTargetState {
+                                    format: TextureFormat::Rgba8Unorm,
+                                    blend: Some(match role {
+                                        LayerRole::Albedo => albedo_blend_state(blend),
+                                        _ => blend_state(blend),
+                                    }),
+                                    write_mask: ColorWrites::ALL,
+                                }),
+                            ],
+                        }),
+                        primitive: PrimitiveState::default(),
+                        depth_stencil: None,
+                        multisample: MultisampleState::default(),
+                        push_constant_ranges: vec![],
+                        zero_initialize_workgroup_memory: false,
+                    },
+                )
+            })
+        });
+
+        Self {
+            layout,
+            pipeline_ids,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the GPU does with `blend` when `src` is drawn over `dst`.
+    fn apply(blend: BlendState, src: Vec4, dst: Vec4) -> Vec4 {
+        let factor = |factor: BlendFactor| match factor {
+            BlendFactor::Zero => Vec4::ZERO,
+            BlendFactor::One => Vec4::ONE,
+            BlendFactor::Dst => dst,
+            BlendFactor::DstAlpha => Vec4::splat(dst.w),
+            BlendFactor::OneMinusSrcAlpha => Vec4::splat(1.0 - src.w),
+            _ => panic!("blend factor {factor:?} isn't modelled by this test"),
+        };
+        let component = |component: BlendComponent| {
+            assert_eq!(component.operation, BlendOperation::Add);
+            src * factor(component.src_factor) + dst * factor(component.dst_factor)
+        };
+        component(blend.color)
+            .xyz()
+            .extend(component(blend.alpha).w)
+    }
+
+    #[test]
+    fn translucent_albedo_layer_keeps_the_albedo_below_premultiplied() {
+        // Half covered by grey paint, with a half covering red albedo layer over it
+        let below = Vec4::new(0.25, 0.25, 0.25, 0.5);
+        let albedo_layer = Vec4::new(0.5, 0.0, 0.0, 0.5);
+
+        let blended = apply(albedo_blend_state(LayerBlend::Normal), albedo_layer, below);
+
+        // The coverage is still the paint's, and the colour half way between grey and red
+        assert_eq!(blended.w, 0.5);
+        assert_eq!(blended.xyz() / blended.w, Vec3::new(0.75, 0.25, 0.25));
+    }
+
+    #[test]
+    fn albedo_layer_over_nothing_leaves_it_empty() {
+        let albedo_layer = Vec4::new(0.5, 0.5, 0.5, 0.5);
+        let blended = apply(
+            albedo_blend_state(LayerBlend::Normal),
+            albedo_layer,
+            Vec4::ZERO,
+        );
+        assert_eq!(blended, Vec4::ZERO);
+    }
+}
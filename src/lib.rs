@@ -0,0 +1,921 @@
+//! 2D global illumination for Bevy using radiance cascades.
+//!
+//! Add [`CascadePlugin`] to your app and a 2D camera. The plugin attaches a paintable canvas and
+//! the GI passes to that camera, and shows the lit result on a sprite covering the window. The
+//! canvas is painted in [`CanvasLayers`], which are blended together before the GI passes run.
+//!
+//! Any other 2D camera given a [`CascadeSettings`] lights the same canvas with GI of its own, for
+//! split screens, minimaps or cameras rendering to an image. It has to render after the camera
+//! that paints, which is the one the plugin attaches to.
+//!
+//! Besides painting, light can come from entities with a [`RadianceEmitter`] or [`RadianceOccluder`],
+//! which are drawn over the canvas every frame.
+//!
+//! Without a display, [`CascadePlugin::with_offscreen_size`] renders into an image instead of the
+//! window, and [`SaveGiOutput`] writes the GI output to a PNG or EXR file.
+
+use bevy::{
+    asset::embedded_asset,
+    core_pipeline::core_2d::graph::{Core2d, Node2d},
+    diagnostic::{DiagnosticPath, FrameCount},
+    ecs::system::SystemParam,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        ExtractSchedule, Render, RenderApp, RenderSet,
+        camera::RenderTarget,
+        extract_component::{ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssetUsages,
+        render_graph::{RenderGraphApp, ViewNodeRunner},
+        render_resource::{binding_types::texture_storage_2d, *},
+        renderer::RenderDevice,
+        view::{RenderLayers, VisibilitySystems},
+    },
+    window::{PrimaryWindow, WindowResized, WindowScaleFactorChanged},
+};
+
+mod canvas;
+mod canvas_file;
+mod capture;
+mod cascades;
+mod contributors;
+mod distance_field;
+mod domain;
+mod fill;
+mod history;
+mod layers;
+mod raymarch;
+pub mod reference;
+mod scene;
+mod shapes;
+mod sky;
+
+pub use canvas::{CanvasPassLabel, CanvasTool, PostProcessSettings, StrokePath, StrokePoint};
+pub use canvas_file::{
+    CanvasLoaded, CanvasSaved, LoadCanvas, SaveCanvas, canvas_emission_path, canvas_layer_path,
+    canvas_layers_path,
+};
+pub use capture::{GiOutputSaved, SaveGiOutput};
+pub use cascades::{CascadeLabel, CascadeMerge, CascadeSettings};
+pub use contributors::{
+    GI_ALBEDO_LAYER, GI_EMISSION_LAYER, GiContributor, GiEmission, GiEmissionProxy, GiSceneCamera,
+    GiSceneImages,
+};
+pub use distance_field::{DistanceFieldImage, JfaLabel, JfaSeedLabel};
+pub use domain::GiDomain;
+pub use history::{CanvasHistory, RedoCanvas, UndoCanvas};
+pub use layers::{CanvasLayer, CanvasLayers, CompositePassLabel, LayerBlend, LayerId, LayerRole};
+pub use raymarch::{RaymarchLabel, RaymarchSettings};
+pub use scene::{RadianceEmitter, RadianceOccluder, RadianceShape, ScenePassLabel};
+pub use sky::SkyLight;
+
+use canvas::{CanvasNode, CanvasPipeline, StrokeBuffer, extract_stroke_path, prepare_stroke_path};
+use canvas_file::{load_canvas, request_canvas_save};
+use capture::request_gi_readback;
+use cascades::{CascadeNode, CascadePipeline, CascadeUniforms, prepare_cascades};
+use contributors::{
+    spawn_gi_scene_cameras, sync_contributor_layers, sync_emission_proxies, sync_gi_scene_cameras,
+};
+use distance_field::{
+    DISTANCE_FIELD_FORMAT, JfaNode, JfaPipeline, JfaSeedNode, JfaTextures, JfaUniforms, prepare_jfa,
+};
+use domain::follow_camera;
+use fill::{PendingFills, hold_strokes_for_fills, start_fill};
+use history::{snapshot_canvas, undo_redo_canvas};
+use layers::{CompositeNode, CompositePipeline};
+use raymarch::{RaymarchNode, RaymarchPipeline};
+use scene::{
+    ExtractedRadianceShapes, SceneNode, ScenePipeline, SceneTextures, extract_radiance_shapes,
+    prepare_scene,
+};
+use shapes::{preview_shapes, track_shapes};
+
+pub mod prelude {
+    pub use crate::{
+        CameraTarget, CanvasHistory, CanvasLayers, CanvasTool, CascadeMerge, CascadePlugin,
+        CascadeSettings, GiBackend, GiContributor, GiDebugView, GiDomain, GiEmission, GiMode,
+        GiOutput, GiOutputSaved, LayerBlend, LayerRole, LoadCanvas, PostProcessSettings,
+        RadianceEmitter, RadianceOccluder, RadianceShape, RaymarchSettings, RedoCanvas, SaveCanvas,
+        SaveGiOutput, SkyLight, StrokePath, StrokePoint, UndoCanvas,
+    };
+}
+
+const CANVAS_SHADER_ASSET_PATH: &str = "embedded://bevy_radiance_cascades/shaders/canvas.wgsl";
+const RAYMARCH_SHADER_ASSET_PATH: &str =
+    "embedded://bevy_radiance_cascades/shaders/raymarching.wgsl";
+const CASCADE_SHADER_ASSET_PATH: &str = "embedded://bevy_radiance_cascades/shaders/cascades.wgsl";
+const JFA_SHADER_ASSET_PATH: &str = "embedded://bevy_radiance_cascades/shaders/jfa.wgsl";
+const SCENE_SHADER_ASSET_PATH: &str = "embedded://bevy_radiance_cascades/shaders/scene.wgsl";
+const COMPOSITE_SHADER_ASSET_PATH: &str =
+    "embedded://bevy_radiance_cascades/shaders/composite.wgsl";
+
+/// Adds radiance cascades GI to a 2D camera.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_radiance_cascades::prelude::*;
+/// App::new().add_plugins((
+///     DefaultPlugins,
+///     CascadePlugin::default()
+///         .with_resolution_scale(0.5)
+///         .with_cascade_count(5),
+/// ));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CascadePlugin {
+    config: GiConfig,
+}
+
+impl Default for CascadePlugin {
+    fn default() -> Self {
+        Self {
+            config: GiConfig {
+                resolution_scale: 1.0,
+                cascade_count: CascadeSettings::default().cascade_count,
+                formats: GiTextureFormats {
+                    // Light can be brighter than 1.0, so emission and the lit output are kept in HDR until Bevy's tonemapping runs.
+                    emission: TextureFormat::Rgba16Float,
+                    radiance: TextureFormat::Rgba16Float,
+                },
+                camera: CameraTarget::default(),
+                backend: GiBackend::default(),
+                offscreen_size: None,
+                undo_budget: 256 * 1024 * 1024,
+                world_margin: None,
+            },
+        }
+    }
+}
+
+impl CascadePlugin {
+    /// Scales the size of the canvas and GI textures relative to the window.
+    pub fn with_resolution_scale(mut self, resolution_scale: f32) -> Self {
+        self.config.resolution_scale = resolution_scale;
+        self
+    }
+
+    /// The cascade count given to cameras that don't have their own `CascadeSettings`.
+    pub fn with_cascade_count(mut self, cascade_count: u32) -> Self {
+        self.config.cascade_count = cascade_count;
+        self
+    }
+
+    /// The format painted emission is stored in.
+    pub fn with_emission_format(mut self, format: TextureFormat) -> Self {
+        self.config.formats.emission = format;
+        self
+    }
+
+    /// The format the lit output of the GI passes is stored in.
+    pub fn with_radiance_format(mut self, format: TextureFormat) -> Self {
+        self.config.formats.radiance = format;
+        self
+    }
+
+    /// Renders to an offscreen image of `size` instead of the primary window, so the GI can run
+    /// without a display. Combine with [`SaveGiOutput`] to write the result to a file.
+    pub fn with_offscreen_size(mut self, size: UVec2) -> Self {
+        self.config.offscreen_size = Some(size);
+        self
+    }
+
+    /// The most memory the undo history of the canvas may take up, in bytes. 0 turns it off.
+    pub fn with_undo_budget(mut self, bytes: usize) -> Self {
+        self.config.undo_budget = bytes;
+        self
+    }
+
+    /// Keeps the canvas and the GI in world space, following the camera as it pans and zooms,
+    /// instead of covering the window around the world's origin. They cover the view and `margin`
+    /// world units beyond each edge of it, so lights just out of view still light it.
+    ///
+    /// The canvas moves in steps of the top cascade's probe spacing, so the probes stay put in the
+    /// world and the lighting doesn't swim. Its pixels keep their size in the world, so zooming in
+    /// shows them larger. Paint that falls outside the canvas as it moves is lost, and undo restores
+    /// the canvas where it was when the stroke was painted.
+    pub fn with_world_margin(mut self, margin: f32) -> Self {
+        self.config.world_margin = Some(margin.max(0.0));
+        self
+    }
+
+    /// Which camera the GI is attached to.
+    pub fn with_camera(mut self, camera: CameraTarget) -> Self {
+        self.config.camera = camera;
+        self
+    }
+
+    /// Whether the GI passes start out as fragment or compute passes. This is the initial value of
+    /// the [`GiBackend`] resource, which can be changed at runtime.
+    pub fn with_backend(mut self, backend: GiBackend) -> Self {
+        self.config.backend = backend;
+        self
+    }
+}
+
+/// Selects the camera [`CascadePlugin`] attaches to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraTarget {
+    /// The 2D camera with the lowest `Camera::order`, so other GI cameras can render after it.
+    #[default]
+    First,
+    /// The 2D camera with this `Camera::order`.
+    Order(isize),
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+struct GiConfig {
+    resolution_scale: f32,
+    cascade_count: u32,
+    formats: GiTextureFormats,
+    camera: CameraTarget,
+    backend: GiBackend,
+    offscreen_size: Option<UVec2>,
+    undo_budget: usize,
+    world_margin: Option<f32>,
+}
+
+/// The formats the pipelines are built for, these have to match the images created in `attach_to_camera`.
+#[derive(Resource, Clone, Copy, Debug)]
+struct GiTextureFormats {
+    emission: TextureFormat,
+    radiance: TextureFormat,
+}
+
+/// Which GI pass writes into the `RaymarchImages`.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug, ExtractResource)]
+pub enum GiMode {
+    /// Brute force, every pixel casts `ray_count` rays of up to `max_steps` steps.
+    Raymarch,
+    #[default]
+    Cascades,
+}
+
+/// How the raymarch and cascade passes are run.
+///
+/// The compute passes need a GI output format that WGSL can write to as a storage texture,
+/// `Rgba16Float`, `Rgba32Float` or `Rgba8Unorm`. With any other format they fall back to
+/// fragment passes.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug, ExtractResource)]
+pub enum GiBackend {
+    /// Fullscreen triangles drawn into render attachments.
+    #[default]
+    Fragment,
+    /// Compute dispatches writing storage textures.
+    Compute,
+}
+
+impl GiBackend {
+    /// The diagnostic the GPU time of `mode` on this backend is recorded under. It's only recorded
+    /// when `RenderDiagnosticsPlugin` is added and the GPU supports timestamp queries.
+    pub fn diagnostic_path(self, mode: GiMode) -> DiagnosticPath {
+        DiagnosticPath::new(format!("render/{}/elapsed_gpu", self.span_name(mode)))
+    }
+
+    pub(crate) fn span_name(self, mode: GiMode) -> &'static str {
+        match (mode, self) {
+            (GiMode::Raymarch, GiBackend::Fragment) => "gi_raymarch_fragment",
+            (GiMode::Raymarch, GiBackend::Compute) => "gi_raymarch_compute",
+            (GiMode::Cascades, GiBackend::Fragment) => "gi_cascades_fragment",
+            (GiMode::Cascades, GiBackend::Compute) => "gi_cascades_compute",
+        }
+    }
+}
+
+/// The storage texture a compute pass writes its output to, bound separately from its inputs.
+pub(crate) struct ComputeOutput {
+    pub(crate) shader_defs: Vec<ShaderDefVal>,
+    pub(crate) layout: BindGroupLayout,
+}
+
+impl ComputeOutput {
+    /// Returns `None` for formats the shaders can't declare as a storage texture.
+    pub(crate) fn new(
+        render_device: &RenderDevice,
+        label: &'static str,
+        format: TextureFormat,
+    ) -> Option<Self> {
+        let format_def = match format {
+            TextureFormat::Rgba16Float => "OUTPUT_RGBA16F",
+            TextureFormat::Rgba32Float => "OUTPUT_RGBA32F",
+            TextureFormat::Rgba8Unorm => "OUTPUT_RGBA8",
+            _ => return None,
+        };
+        let layout = render_device.create_bind_group_layout(
+            label,
+            &BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                texture_storage_2d(format, StorageTextureAccess::WriteOnly),
+            ),
+        );
+        Some(Self {
+            shader_defs: vec!["COMPUTE".into(), format_def.into()],
+            layout,
+        })
+    }
+}
+
+/// What the GI sprite shows instead of the lit canvas, for debugging the GI passes.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum GiDebugView {
+    #[default]
+    None,
+    DistanceField,
+}
+
+/// The render layer the output of the first GI camera is drawn on. Every GI camera after it takes
+/// the next layer up.
+pub const GI_OUTPUT_LAYER: usize = 32;
+
+/// The sprite a GI camera's lit canvas is shown on, stretched over the [`GiDomain`].
+///
+/// Every GI camera has one, on a render layer of its own from [`GI_OUTPUT_LAYER`] up. That layer is
+/// added to the camera's `RenderLayers`, so other cameras don't see it.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct GiOutput {
+    /// The camera whose GI this shows.
+    pub camera: Entity,
+}
+
+/// The canvas the GI passes read, with every visible layer of [`CanvasLayers`] blended together.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct CanvasImages {
+    pub front: CanvasGBuffer,
+    pub back: CanvasGBuffer,
+    pub target_front: bool,
+}
+
+impl CanvasImages {
+    /// The side of the ping pong written last frame.
+    pub fn source(&self) -> &CanvasGBuffer {
+        if self.target_front {
+            &self.back
+        } else {
+            &self.front
+        }
+    }
+
+    /// The side of the ping pong the composite pass writes to this frame, which the scene pass draws over.
+    pub fn target(&self) -> &CanvasGBuffer {
+        if self.target_front {
+            &self.front
+        } else {
+            &self.back
+        }
+    }
+}
+
+/// The material channels of the canvas, painted together in one pass.
+#[derive(Clone)]
+pub struct CanvasGBuffer {
+    /// Radiance given off by the surface.
+    pub emission: Handle<Image>,
+    /// Diffuse colour in rgb. Alpha is the occluder mask, anything above 0.5 blocks light. Paint at or
+    /// below 0.5 is translucent, and lets through its colour the fainter it is.
+    pub albedo: Handle<Image>,
+}
+
+/// The images a GI camera's lit canvas is written to, one a frame, with the other kept as history.
+#[derive(Component, Clone, ExtractComponent)]
+pub struct RaymarchImages {
+    pub a: Handle<Image>,
+    pub b: Handle<Image>,
+    pub ping: bool,
+}
+
+impl RaymarchImages {
+    /// The image the GI passes write to this frame, once `ping_pong_canvas` has flipped the ping pong.
+    pub fn target(&self) -> &Handle<Image> {
+        if self.ping { &self.a } else { &self.b }
+    }
+}
+
+impl Plugin for CascadePlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shaders/canvas.wgsl");
+        embedded_asset!(app, "shaders/raymarching.wgsl");
+        embedded_asset!(app, "shaders/cascades.wgsl");
+        embedded_asset!(app, "shaders/jfa.wgsl");
+        embedded_asset!(app, "shaders/scene.wgsl");
+        embedded_asset!(app, "shaders/composite.wgsl");
+
+        app.add_plugins((
+            ExtractComponentPlugin::<PostProcessSettings>::default(),
+            UniformComponentPlugin::<PostProcessSettings>::default(),
+            ExtractComponentPlugin::<RaymarchSettings>::default(),
+            UniformComponentPlugin::<RaymarchSettings>::default(),
+            ExtractResourcePlugin::<CanvasImages>::default(),
+            ExtractResourcePlugin::<CanvasLayers>::default(),
+            ExtractComponentPlugin::<RaymarchImages>::default(),
+            ExtractComponentPlugin::<CascadeSettings>::default(),
+            ExtractComponentPlugin::<SkyLight>::default(),
+            UniformComponentPlugin::<SkyLight>::default(),
+            ExtractResourcePlugin::<GiMode>::default(),
+            ExtractResourcePlugin::<GiBackend>::default(),
+            ExtractResourcePlugin::<DistanceFieldImage>::default(),
+            ExtractResourcePlugin::<GiSceneImages>::default(),
+        ));
+        app.insert_resource(self.config)
+            .init_resource::<GiMode>()
+            .insert_resource(self.config.backend)
+            .init_resource::<GiDebugView>()
+            // Lets scene files spawn emitters and occluders
+            .register_type::<RadianceEmitter>()
+            .register_type::<RadianceOccluder>()
+            .add_event::<SaveGiOutput>()
+            .add_event::<GiOutputSaved>()
+            .add_event::<SaveCanvas>()
+            .add_event::<CanvasSaved>()
+            .add_event::<LoadCanvas>()
+            .add_event::<CanvasLoaded>()
+            .add_event::<UndoCanvas>()
+            .add_event::<RedoCanvas>()
+            .insert_resource(CanvasHistory::new(self.config.undo_budget))
+            .init_resource::<PendingFills>()
+            // Before anything places the brush, so it's given in this frame's domain
+            .add_systems(PreUpdate, follow_camera.run_if(resource_exists::<GiDomain>))
+            .add_systems(
+                Update,
+                (
+                    attach_to_camera.run_if(not(resource_exists::<CanvasImages>)),
+                    (
+                        attach_gi_views,
+                        ping_pong_canvas,
+                        resize_gi_images,
+                        update_raymarch_settings,
+                        sync_gi_scene_cameras,
+                    )
+                        .chain()
+                        .run_if(resource_exists::<CanvasImages>),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    (sync_contributor_layers, sync_emission_proxies)
+                        .before(VisibilitySystems::CheckVisibility),
+                    request_gi_readback.run_if(resource_exists::<CanvasImages>),
+                    preview_shapes,
+                    (
+                        load_canvas,
+                        undo_redo_canvas,
+                        track_shapes,
+                        start_fill,
+                        snapshot_canvas,
+                        request_canvas_save,
+                        // Last, so the systems above still see the stroke as it was made
+                        hold_strokes_for_fills,
+                    )
+                        .chain()
+                        .run_if(resource_exists::<CanvasImages>),
+                ),
+            );
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        // The canvas, scene and distance field passes only run for the camera that paints, the GI
+        // passes run for every camera with its own textures.
+        render_app
+            .add_render_graph_node::<ViewNodeRunner<CanvasNode>>(Core2d, CanvasPassLabel)
+            .add_render_graph_node::<ViewNodeRunner<CompositeNode>>(Core2d, CompositePassLabel)
+            .add_render_graph_node::<ViewNodeRunner<SceneNode>>(Core2d, ScenePassLabel)
+            .add_render_graph_node::<ViewNodeRunner<JfaSeedNode>>(Core2d, JfaSeedLabel)
+            .add_render_graph_node::<ViewNodeRunner<JfaNode>>(Core2d, JfaLabel)
+            .add_render_graph_node::<ViewNodeRunner<RaymarchNode>>(Core2d, RaymarchLabel)
+            .add_render_graph_node::<ViewNodeRunner<CascadeNode>>(Core2d, CascadeLabel)
+            .add_render_graph_edges(
+                Core2d,
+                (
+                    Node2d::PostProcessing,
+                    CanvasPassLabel,
+                    CompositePassLabel,
+                    ScenePassLabel,
+                    JfaSeedLabel,
+                    JfaLabel,
+                    RaymarchLabel,
+                    CascadeLabel,
+                    Node2d::EndMainPassPostProcessing,
+                ),
+            );
+
+        render_app
+            .insert_resource(self.config.formats)
+            .init_resource::<CascadeUniforms>()
+            .init_resource::<JfaUniforms>()
+            .init_resource::<JfaTextures>()
+            .init_resource::<ExtractedRadianceShapes>()
+            .init_resource::<SceneTextures>()
+            .init_resource::<StrokeBuffer>()
+            .add_systems(
+                ExtractSchedule,
+                (extract_radiance_shapes, extract_stroke_path),
+            )
+            .add_systems(
+                Render,
+                (
+                    prepare_stroke_path,
+                    prepare_scene,
+                    prepare_jfa,
+                    prepare_cascades,
+                )
+                    .in_set(RenderSet::PrepareResources),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<CanvasPipeline>();
+        render_app.init_resource::<CompositePipeline>();
+        render_app.init_resource::<ScenePipeline>();
+        render_app.init_resource::<RaymarchPipeline>();
+        render_app.init_resource::<CascadePipeline>();
+        render_app.init_resource::<JfaPipeline>();
+    }
+}
+
+/// The app's own 2D cameras, leaving out the ones the plugin renders the GI scene with.
+type AppCamera2d = (With<Camera2d>, Without<GiSceneCamera>);
+
+/// Creates the canvas and GI images once the configured camera exists, and gives it any settings it's missing.
+fn attach_to_camera(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    config: Res<GiConfig>,
+    mut cameras: Query<(Entity, &mut Camera), AppCamera2d>,
+    window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let found = match config.camera {
+        CameraTarget::First => cameras.iter_mut().min_by_key(|(_, camera)| camera.order),
+        CameraTarget::Order(order) => cameras.iter_mut().find(|(_, camera)| camera.order == order),
+    };
+    let Some((camera, mut camera_component)) = found else {
+        return;
+    };
+    let order = camera_component.order;
+    // The size of the world the canvas covers, in logical pixels
+    let display_size = match (config.offscreen_size, window.single()) {
+        (Some(size), _) => size.as_vec2(),
+        (None, Ok(window)) => window.size(),
+        (None, Err(_)) => return,
+    };
+
+    if let Some(size) = config.offscreen_size {
+        // There may be no window to render to, so the camera renders into an image of its own
+        let mut target = Image::new_fill(
+            Extent3d {
+                width: size.x.max(1),
+                height: size.y.max(1),
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 0],
+            TextureFormat::bevy_default(),
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        target.texture_descriptor.usage = TextureUsages::COPY_DST
+            | TextureUsages::COPY_SRC
+            | TextureUsages::TEXTURE_BINDING
+            | TextureUsages::RENDER_ATTACHMENT;
+        camera_component.target = RenderTarget::Image(images.add(target).into());
+    }
+
+    commands.entity(camera).insert_if_new((
+        PostProcessSettings::default(),
+        StrokePath::default(),
+        RaymarchSettings::default(),
+        CascadeSettings {
+            cascade_count: config.cascade_count,
+            ..Default::default()
+        },
+    ));
+
+    // The canvas covers the window, scaled by the resolution scale
+    let mut image = Image::new_fill(
+        canvas_size(display_size, config.resolution_scale),
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    );
+    // The passes draw into these images and sample them, compute passes write them as storage, new
+    // paint is uploaded to them and COPY_SRC lets them be read back to be saved
+    image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING
+        | TextureUsages::RENDER_ATTACHMENT;
+
+    let emission_image = reformat_empty_image(&image, config.formats.emission);
+
+    let a = CanvasGBuffer {
+        emission: images.add(emission_image.clone()),
+        albedo: images.add(image.clone()),
+    };
+    // The distance field only needs one channel, and it's rewritten from scratch every frame
+    let mut distance_field = reformat_empty_image(&image, DISTANCE_FIELD_FORMAT);
+    // R16Float can't be used as a storage texture
+    distance_field.texture_descriptor.usage =
+        TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT;
+    let distance_field = images.add(distance_field);
+
+    // Both GI scene images are rendered in HDR, so emission above 1.0 survives
+    let gi_scene_image = reformat_empty_image(&image, TextureFormat::Rgba16Float);
+    let gi_scene = GiSceneImages {
+        albedo: images.add(gi_scene_image.clone()),
+        emission: images.add(gi_scene_image),
+    };
+
+    let b = CanvasGBuffer {
+        emission: images.add(emission_image.clone()),
+        albedo: images.add(image.clone()),
+    };
+
+    // The first layer is painted into images of its own, the canvas is blended from the layers
+    let layer_front = CanvasGBuffer {
+        emission: images.add(emission_image.clone()),
+        albedo: images.add(image.clone()),
+    };
+    let layer_back = CanvasGBuffer {
+        emission: images.add(emission_image),
+        albedo: images.add(image),
+    };
+
+    // The canvas pass reads one side and writes the other, swapping every frame
+    commands.insert_resource(CanvasImages {
+        front: a,
+        back: b,
+        target_front: false,
+    });
+    commands.insert_resource(CanvasLayers::new(layer_front, layer_back));
+
+    commands.insert_resource(DistanceFieldImage {
+        image: distance_field,
+    });
+    commands.insert_resource(GiDomain::fixed(display_size));
+
+    commands.insert_resource(gi_scene.clone());
+    spawn_gi_scene_cameras(&mut commands, &gi_scene, order);
+}
+
+/// Gives every 2D camera with [`CascadeSettings`] the images its GI is written to, and the sprite
+/// they're shown on, and takes them away again from cameras that lose their settings.
+fn attach_gi_views(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut next_layer: Local<usize>,
+    config: Res<GiConfig>,
+    canvas_images: Res<CanvasImages>,
+    domain: Res<GiDomain>,
+    views: GiViews,
+) {
+    let GiViews {
+        cameras,
+        gi_views,
+        outputs,
+    } = views;
+    for (output, gi_output) in &outputs {
+        if !gi_views.contains(gi_output.camera) {
+            commands.entity(output).despawn();
+            if let Ok(mut camera) = commands.get_entity(gi_output.camera) {
+                camera.remove::<RaymarchImages>();
+            }
+        }
+    }
+
+    // The GI images are the size of the canvas they light
+    let Some(canvas) = images.get(&canvas_images.target().albedo) else {
+        return;
+    };
+    let radiance_image = reformat_empty_image(canvas, config.formats.radiance);
+
+    for (camera, render_layers) in &cameras {
+        let layer = GI_OUTPUT_LAYER + *next_layer;
+        *next_layer += 1;
+
+        // The raymarch pass writes one while reading last frame's output from the other as history
+        let raymarch_images = RaymarchImages {
+            a: images.add(radiance_image.clone()),
+            b: images.add(radiance_image.clone()),
+            ping: false,
+        };
+        commands.spawn((
+            Sprite {
+                image: raymarch_images.a.clone(),
+                custom_size: Some(domain.size),
+                ..Default::default()
+            },
+            Transform::from_translation(domain.center.extend(0.0)),
+            RenderLayers::layer(layer),
+            GiOutput { camera },
+        ));
+        commands
+            .entity(camera)
+            .insert((
+                raymarch_images,
+                render_layers.cloned().unwrap_or_default().with(layer),
+            ))
+            .insert_if_new(RaymarchSettings::default());
+    }
+}
+
+/// The size of the canvas and GI images for a display of `size` logical pixels.
+fn canvas_size(size: Vec2, resolution_scale: f32) -> Extent3d {
+    Extent3d {
+        width: ((size.x * resolution_scale).round() as u32).max(1),
+        height: ((size.y * resolution_scale).round() as u32).max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Copies an empty image into another format, resizing its data to match.
+fn reformat_empty_image(image: &Image, format: TextureFormat) -> Image {
+    let mut image = image.clone();
+    let texel_count = (image.width() * image.height()) as usize;
+    let texel_size = format.block_copy_size(None).unwrap() as usize;
+    image.texture_descriptor.format = format;
+    image.data = Some(vec![0; texel_count * texel_size]);
+    image
+}
+
+/// Keeps the raymarcher stepping one canvas pixel at a time, whatever the resolution scale is, and
+/// moves its noise on every frame.
+fn update_raymarch_settings(
+    canvas_images: Res<CanvasImages>,
+    images: Res<Assets<Image>>,
+    frame_count: Res<FrameCount>,
+    mut settings: Query<&mut RaymarchSettings>,
+) {
+    let Some(canvas) = images.get(&canvas_images.target().albedo) else {
+        return;
+    };
+    for mut raymarch_settings in &mut settings {
+        raymarch_settings.resolution = canvas.size_f32();
+        raymarch_settings.frame_index = frame_count.0;
+    }
+}
+
+fn ping_pong_canvas(
+    mut canvas_images: ResMut<CanvasImages>,
+    mut layers: ResMut<CanvasLayers>,
+    mut views: Query<&mut RaymarchImages>,
+    distance_field: Res<DistanceFieldImage>,
+    debug_view: Res<GiDebugView>,
+    mut outputs: Query<(&GiOutput, &mut Sprite)>,
+) {
+    for (output, mut sprite) in &mut outputs {
+        let Ok(raymarch_images) = views.get(output.camera) else {
+            continue;
+        };
+        let image = if *debug_view == GiDebugView::DistanceField {
+            &distance_field.image
+        } else if raymarch_images.ping {
+            &raymarch_images.a
+        } else {
+            &raymarch_images.b
+        };
+        sprite.image = image.clone();
+    }
+    canvas_images.target_front = !canvas_images.target_front;
+    layers.target_front = canvas_images.target_front;
+    for mut raymarch_images in &mut views {
+        raymarch_images.ping = !raymarch_images.ping;
+    }
+}
+
+/// 2D cameras with settings that don't have their GI images yet.
+type WaitingForGi = (
+    With<Camera2d>,
+    With<CascadeSettings>,
+    Without<RaymarchImages>,
+    Without<GiSceneCamera>,
+);
+
+/// The cameras [`attach_gi_views`] gives GI to or takes it away from, and the sprites their GI is
+/// shown on.
+#[derive(SystemParam)]
+struct GiViews<'w, 's> {
+    cameras: Query<'w, 's, (Entity, Option<&'static RenderLayers>), WaitingForGi>,
+    gi_views: Query<'w, 's, (), With<CascadeSettings>>,
+    outputs: Query<'w, 's, (Entity, &'static GiOutput)>,
+}
+
+/// Every image that is kept at the size of the canvas.
+#[derive(SystemParam)]
+struct CanvasSizedImages<'w, 's> {
+    canvas_images: Res<'w, CanvasImages>,
+    layers: Res<'w, CanvasLayers>,
+    views: Query<'w, 's, &'static RaymarchImages>,
+    distance_field: Res<'w, DistanceFieldImage>,
+    gi_scene: Res<'w, GiSceneImages>,
+}
+
+/// Reads whether the primary window changed size since last frame.
+#[derive(SystemParam)]
+struct WindowChanges<'w, 's> {
+    resized: EventReader<'w, 's, WindowResized>,
+    scale_factor_changed: EventReader<'w, 's, WindowScaleFactorChanged>,
+    window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+}
+
+impl WindowChanges<'_, '_> {
+    /// The window's logical size, if it was resized or its scale factor changed since last frame.
+    fn new_size(&mut self) -> Option<Vec2> {
+        let changed = self.resized.read().count() + self.scale_factor_changed.read().count() > 0;
+        changed
+            .then(|| self.window.single().ok().map(Window::size))
+            .flatten()
+    }
+}
+
+/// Reallocates the canvas and GI images after the window is resized or its scale factor changes.
+///
+/// Only the side of each ping pong that is written this frame is resized. The canvas pass copies the
+/// other side into it pixel for pixel, so the painting survives wherever it still fits, and the next
+/// frame resizes the remaining side the same way.
+fn resize_gi_images(
+    mut window_changes: WindowChanges,
+    mut pending_size: Local<Option<Extent3d>>,
+    config: Res<GiConfig>,
+    sized: CanvasSizedImages,
+    mut domain: ResMut<GiDomain>,
+    mut images: ResMut<Assets<Image>>,
+    mut outputs: Query<&mut Sprite, With<GiOutput>>,
+) {
+    let CanvasSizedImages {
+        canvas_images,
+        layers,
+        views,
+        distance_field,
+        gi_scene,
+    } = &sized;
+    let new_window_size = window_changes.new_size();
+    if let Some(texels) = domain.texels {
+        // A domain that follows the camera is sized by `follow_camera`, as the view changes
+        let size = Extent3d {
+            width: texels.x.max(1),
+            height: texels.y.max(1),
+            depth_or_array_layers: 1,
+        };
+        if images
+            .get(&canvas_images.target().albedo)
+            .is_some_and(|image| image.texture_descriptor.size != size)
+        {
+            *pending_size = Some(size);
+        }
+    } else if let Some(window_size) = new_window_size
+        // An offscreen canvas keeps its size whatever the window does
+        && config.offscreen_size.is_none()
+    {
+        *pending_size = Some(canvas_size(window_size, config.resolution_scale));
+        for mut sprite in &mut outputs {
+            sprite.custom_size = Some(window_size);
+        }
+        domain.size = window_size;
+    }
+    let Some(size) = *pending_size else {
+        return;
+    };
+
+    let target = canvas_images.target();
+    let layer_targets = layers
+        .iter()
+        .map(|layer| layers.target(layer))
+        .flat_map(|gbuffer| [&gbuffer.emission, &gbuffer.albedo]);
+    for handle in [
+        &target.emission,
+        &target.albedo,
+        &distance_field.image,
+        &gi_scene.albedo,
+        &gi_scene.emission,
+    ]
+    .into_iter()
+    .chain(layer_targets)
+    .chain(views.iter().map(RaymarchImages::target))
+    {
+        // Only take the image mutably when it needs resizing, so unchanged images aren't uploaded again
+        if images
+            .get(handle)
+            .is_some_and(|image| image.texture_descriptor.size != size)
+        {
+            images.get_mut(handle).unwrap().resize(size);
+        }
+    }
+
+    // Layers added since the resize started have to catch up as well
+    let mut sources = layers
+        .iter()
+        .map(|layer| &layers.source(layer).albedo)
+        .chain([&canvas_images.source().albedo]);
+    let sources_resized = sources.all(|handle| {
+        images
+            .get(handle)
+            .is_some_and(|image| image.texture_descriptor.size == size)
+    });
+    if sources_resized {
+        *pending_size = None;
+    }
+}
@@ -0,0 +1,311 @@
+use bevy::{
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        diagnostic::RecordDiagnostics,
+        extract_component::{ComponentUniforms, DynamicUniformIndex, ExtractComponent},
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{
+            binding_types::{texture_2d, uniform_buffer},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::GpuImage,
+    },
+};
+
+use crate::{
+    ComputeOutput, GiBackend, GiMode, GiTextureFormats, RAYMARCH_SHADER_ASSET_PATH, RaymarchImages,
+    SkyLight, distance_field::DistanceFieldImage, scene::SceneTextures,
+};
+
+/// The brute force GI pass, used when `GiMode::Raymarch` is selected.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct RaymarchLabel;
+
+#[derive(Default)]
+pub(crate) struct RaymarchNode;
+
+/// Settings for the brute force GI pass, for the camera they're on. The sky comes from the camera's
+/// [`SkyLight`].
+#[derive(Component, Clone, Copy, ExtractComponent, ShaderType, AsBindGroup)]
+#[require(SkyLight)]
+pub struct RaymarchSettings {
+    /// The size of the canvas in pixels. This is kept up to date by the plugin.
+    pub resolution: Vec2,
+    pub ray_count: u32,
+    pub max_steps: u32,
+    /// Changes the ray angles every frame. This is kept up to date by the plugin.
+    pub frame_index: u32,
+    /// How much of last frame's output is kept, as an exponential moving average. 0.0 turns the
+    /// accumulation off. The history is thrown away wherever the scene changed.
+    pub history_blend: f32,
+    /// How much of the light reaching a surface it passes on to the rest of the canvas, read back from
+    /// last frame's output. Every frame adds one more bounce, so light spills around corners over a
+    /// few frames. 0.0 only gathers the light coming straight from emitters, and values near 1.0 may
+    /// never settle if the albedo is bright too.
+    pub bounce_strength: f32,
+    /// How many canvas pixels the canvas moved by since last frame, where the history is read from.
+    /// This is kept up to date by the plugin.
+    pub scroll: IVec2,
+}
+
+impl Default for RaymarchSettings {
+    fn default() -> Self {
+        Self {
+            resolution: Vec2::ZERO,
+            ray_count: 16,
+            max_steps: 128,
+            frame_index: 0,
+            history_blend: 0.9,
+            bounce_strength: 0.0,
+            scroll: IVec2::ZERO,
+        }
+    }
+}
+
+impl ViewNode for RaymarchNode {
+    type ViewQuery = (
+        &'static RaymarchImages,
+        &'static DynamicUniformIndex<RaymarchSettings>,
+        &'static DynamicUniformIndex<SkyLight>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (raymarch_images, settings_index, sky_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if *world.resource::<GiMode>() != GiMode::Raymarch {
+            return Ok(());
+        }
+
+        let raymarch_pipeline = world.resource::<RaymarchPipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(raymarch_pipeline.pipeline_id)
+        else {
+            return Ok(());
+        };
+        // Falls back to the fragment pass until the compute pipeline is ready, or if it can't be built
+        let compute_pipeline = raymarch_pipeline
+            .compute_pipeline_id
+            .filter(|_| *world.resource::<GiBackend>() == GiBackend::Compute)
+            .and_then(|id| pipeline_cache.get_compute_pipeline(id));
+
+        let settings_uniforms = world.resource::<ComponentUniforms<RaymarchSettings>>();
+        let sky_uniforms = world.resource::<ComponentUniforms<SkyLight>>();
+        let (Some(settings_binding), Some(sky_binding)) = (
+            settings_uniforms.uniforms().binding(),
+            sky_uniforms.uniforms().binding(),
+        ) else {
+            return Ok(());
+        };
+        let scene_textures = world.resource::<SceneTextures>();
+        let (Some(scene), Some(previous_scene)) =
+            (scene_textures.gbuffer(), scene_textures.previous_gbuffer())
+        else {
+            return Ok(());
+        };
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        // The GI output has its own ping pong, separate from the canvas, so the light never feeds back
+        // into the paint. Last frame's output, the one the sprite is showing, is the history
+        let (dst, history) = if raymarch_images.ping {
+            (&raymarch_images.a, &raymarch_images.b)
+        } else {
+            (&raymarch_images.b, &raymarch_images.a)
+        };
+        // A camera that just got its GI has to wait for its images to reach the GPU
+        let (Some(dst_image), Some(history_image)) = (gpu_images.get(dst), gpu_images.get(history))
+        else {
+            return Ok(());
+        };
+        let dst_view = &dst_image.texture_view;
+        let history_view = &history_image.texture_view;
+        let distance_view = &gpu_images
+            .get(&world.resource::<DistanceFieldImage>().image)
+            .unwrap()
+            .texture_view;
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "raymarch_bind_group",
+            &raymarch_pipeline.layout,
+            &BindGroupEntries::sequential((
+                // The canvas with the emitters and occluders drawn over it
+                &scene.emission.default_view,
+                &scene.albedo.default_view,
+                settings_binding.clone(),
+                distance_view,
+                history_view,
+                &previous_scene.emission.default_view,
+                &previous_scene.albedo.default_view,
+                sky_binding.clone(),
+            )),
+        );
+
+        let diagnostics = render_context.diagnostic_recorder();
+
+        if let (Some(compute_pipeline), Some(compute_output)) =
+            (compute_pipeline, &raymarch_pipeline.compute_output)
+        {
+            let output_bind_group = render_context.render_device().create_bind_group(
+                "raymarch_output_bind_group",
+                &compute_output.layout,
+                &BindGroupEntries::single(dst_view),
+            );
+
+            let mut compute_pass =
+                render_context
+                    .command_encoder()
+                    .begin_compute_pass(&ComputePassDescriptor {
+                        label: Some("raymarch_compute_pass"),
+                        timestamp_writes: None,
+                    });
+            let pass_span = diagnostics.pass_span(
+                &mut compute_pass,
+                GiBackend::Compute.span_name(GiMode::Raymarch),
+            );
+
+            compute_pass.set_pipeline(compute_pipeline);
+            compute_pass.set_bind_group(
+                0,
+                &bind_group,
+                &[settings_index.index(), sky_index.index()],
+            );
+            compute_pass.set_bind_group(1, &output_bind_group, &[]);
+            // One invocation per pixel, in the shader's 8x8 workgroups
+            compute_pass.dispatch_workgroups(
+                dst_image.texture.width().div_ceil(8),
+                dst_image.texture.height().div_ceil(8),
+                1,
+            );
+
+            pass_span.end(&mut compute_pass);
+            return Ok(());
+        }
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("raymarch_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: dst_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        let pass_span = diagnostics.pass_span(
+            &mut render_pass,
+            GiBackend::Fragment.span_name(GiMode::Raymarch),
+        );
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[settings_index.index(), sky_index.index()]);
+        render_pass.draw(0..3, 0..1);
+
+        pass_span.end(&mut render_pass);
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct RaymarchPipeline {
+    layout: BindGroupLayout,
+    pipeline_id: CachedRenderPipelineId,
+    /// Only built when the GI output format can be written from a compute shader.
+    compute_output: Option<ComputeOutput>,
+    compute_pipeline_id: Option<CachedComputePipelineId>,
+}
+
+impl FromWorld for RaymarchPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let formats = *world.resource::<GiTextureFormats>();
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "raymarch_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                // Shared by the fragment and compute passes
+                ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                (
+                    // The emission channel of the canvas
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // The albedo and occlusion channels of the canvas
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // The settings uniform that will control the effect
+                    uniform_buffer::<RaymarchSettings>(true),
+                    // The distance field from the JFA passes, used to skip empty space
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Last frame's output, accumulated into this frame's
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Last frame's scene, to reject the history where it changed
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // The camera's sky, shared with the cascades
+                    uniform_buffer::<SkyLight>(true),
+                ),
+            ),
+        );
+
+        // Get the shader handle
+        let shader = world.load_asset(RAYMARCH_SHADER_ASSET_PATH);
+
+        let compute_output =
+            ComputeOutput::new(render_device, "raymarch_output_layout", formats.radiance);
+
+        let pipeline_cache = world.resource_mut::<PipelineCache>();
+        let compute_pipeline_id = compute_output.as_ref().map(|output| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("raymarch_compute_pipeline".into()),
+                layout: vec![layout.clone(), output.layout.clone()],
+                push_constant_ranges: vec![],
+                shader: shader.clone(),
+                shader_defs: output.shader_defs.clone(),
+                entry_point: "raymarch_compute".into(),
+                zero_initialize_workgroup_memory: false,
+            })
+        });
+
+        let pipeline_id = pipeline_cache
+            // This will add the pipeline to the cache and queue its creation
+            .queue_render_pipeline(RenderPipelineDescriptor {
+                label: Some("raymarch_pipeline".into()),
+                layout: vec![layout.clone()],
+                // This will setup a fullscreen triangle for the vertex state
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: shader.clone(),
+                    shader_defs: vec![],
+                    entry_point: "fragment".into(),
+                    targets: vec![Some(ColorTargetState {
+                        format: formats.radiance,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                // All of the following properties are not important for this effect so just use the default values.
+                // This struct doesn't have the Default trait implemented because not all fields can have a default value.
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                push_constant_ranges: vec![],
+                zero_initialize_workgroup_memory: false,
+            });
+
+        Self {
+            layout,
+            pipeline_id,
+            compute_output,
+            compute_pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! A CPU version of the brute force raymarcher in `raymarching.wgsl`.
+//!
+//! It follows the shader step for step, with the same ray angles, noise, step rule and thresholds,
+//! so its output can be trusted as the image the GPU pass should produce. Only a single frame is
+//! traced, as if `history_blend` were 0.0. [`raymarch_with_previous`] traces the frame after another
+//! one, for the light bounced back by `bounce_strength`. [`march_cascade_interval`] follows the
+//! marching of a single interval in `cascades.wgsl` the same way.
+
+use std::f32::consts::TAU;
+
+use bevy::math::{UVec2, Vec2, Vec3, Vec4};
+
+use crate::{CascadeSettings, RaymarchSettings, SkyLight, canvas::MEDIUM_MAX_ALPHA};
+
+/// The channels of the canvas the raymarcher reads, one value per pixel in rows from the top.
+#[derive(Clone, Debug)]
+pub struct ReferenceCanvas {
+    pub size: UVec2,
+    /// Radiance given off by the surface, premultiplied like the albedo. Translucent pixels give it
+    /// off for every pixel a ray crosses.
+    pub emission: Vec<Vec4>,
+    /// Diffuse colour in rgb, premultiplied by alpha. Alpha is the occluder mask, anything above 0.5
+    /// blocks light and anything else above 0.0 is translucent.
+    pub albedo: Vec<Vec4>,
+    /// What rays that leave the canvas see, the [`SkyLight`] of the camera.
+    pub sky: SkyLight,
+}
+
+impl ReferenceCanvas {
+    /// An empty canvas of `size` pixels.
+    pub fn new(size: UVec2) -> Self {
+        let pixel_count = (size.x * size.y) as usize;
+        Self {
+            size,
+            emission: vec![Vec4::ZERO; pixel_count],
+            albedo: vec![Vec4::ZERO; pixel_count],
+            sky: SkyLight::default(),
+        }
+    }
+
+    /// Paints every pixel whose centre is within `radius` of `center`.
+    pub fn fill_circle(&mut self, center: Vec2, radius: f32, emission: Vec4, albedo: Vec4) {
+        self.fill(emission, albedo, |position| {
+            position.distance(center) <= radius
+        });
+    }
+
+    /// Paints every pixel whose centre is between `min` and `max`.
+    pub fn fill_rect(&mut self, min: Vec2, max: Vec2, emission: Vec4, albedo: Vec4) {
+        self.fill(emission, albedo, |position| {
+            position.x >= min.x && position.y >= min.y && position.x <= max.x && position.y <= max.y
+        });
+    }
+
+    /// Layers premultiplied canvas paint over every pixel whose centre is between `min` and `max`,
+    /// the way `scene.wgsl` puts the canvas over sprites and meshes.
+    pub fn paint_rect(&mut self, min: Vec2, max: Vec2, emission: Vec4, albedo: Vec4) {
+        self.blend(
+            |position| {
+                position.x >= min.x
+                    && position.y >= min.y
+                    && position.x <= max.x
+                    && position.y <= max.y
+            },
+            |below_emission, below_albedo| {
+                *below_emission = emission + (1.0 - albedo.w) * *below_emission;
+                *below_albedo = albedo + (1.0 - albedo.w) * *below_albedo;
+            },
+        );
+    }
+
+    fn fill(&mut self, emission: Vec4, albedo: Vec4, covers: impl Fn(Vec2) -> bool) {
+        self.blend(covers, |below_emission, below_albedo| {
+            *below_emission = emission;
+            *below_albedo = albedo;
+        });
+    }
+
+    fn blend(&mut self, covers: impl Fn(Vec2) -> bool, blend: impl Fn(&mut Vec4, &mut Vec4)) {
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                if covers(Vec2::new(x as f32 + 0.5, y as f32 + 0.5)) {
+                    let index = (y * self.size.x + x) as usize;
+                    blend(&mut self.emission[index], &mut self.albedo[index]);
+                }
+            }
+        }
+    }
+
+    /// The distance from every pixel centre to the nearest solid or translucent one, divided by the
+    /// longest side of the canvas. This is what the jump flood converges to, 1.0 everywhere when the
+    /// canvas is empty.
+    pub fn distance_field(&self) -> Vec<f32> {
+        let longest_side = self.size.x.max(self.size.y) as f32;
+        let solid: Vec<Vec2> = self
+            .pixel_centers()
+            .filter(|&(index, _)| self.albedo[index].w > 0.0)
+            .map(|(_, position)| position)
+            .collect();
+
+        self.pixel_centers()
+            .map(|(_, position)| {
+                solid
+                    .iter()
+                    .map(|seed| seed.distance(position) / longest_side)
+                    .reduce(f32::min)
+                    .unwrap_or(1.0)
+            })
+            .collect()
+    }
+
+    fn pixel_centers(&self) -> impl Iterator<Item = (usize, Vec2)> + '_ {
+        (0..self.size.y).flat_map(move |y| {
+            (0..self.size.x).map(move |x| {
+                (
+                    (y * self.size.x + x) as usize,
+                    Vec2::new(x as f32 + 0.5, y as f32 + 0.5),
+                )
+            })
+        })
+    }
+
+    /// Reads `channel` at `uv` the way the shader's `uv_to_pixel` does, nearest and clamped to the edge.
+    fn sample<T: Copy>(&self, channel: &[T], uv: Vec2) -> T {
+        let x = ((uv.x * self.size.x as f32).floor() as i64).clamp(0, self.size.x as i64 - 1);
+        let y = ((uv.y * self.size.y as f32).floor() as i64).clamp(0, self.size.y as i64 - 1);
+        channel[y as usize * self.size.x as usize + x as usize]
+    }
+}
+
+/// WGSL's `fract`, which unlike `f32::fract` is always positive.
+fn fract(value: f32) -> f32 {
+    value - value.floor()
+}
+
+fn rand(input: Vec2) -> f32 {
+    let magic_vec = Vec2::new(12.9898, 78.233);
+    fract((input.dot(magic_vec) * 43_758.547).sin())
+}
+
+fn out_of_bounds(uv: Vec2) -> bool {
+    uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0
+}
+
+const MIN_TRANSMITTANCE: f32 = 0.01;
+
+fn is_medium(material: Vec4) -> bool {
+    material.w > 0.0 && material.w <= MEDIUM_MAX_ALPHA
+}
+
+fn medium_transmittance(material: Vec4, length: f32) -> Vec3 {
+    let per_pixel = Vec3::ONE.lerp(
+        material.truncate() / material.w,
+        material.w / MEDIUM_MAX_ALPHA,
+    );
+    per_pixel.max(Vec3::splat(1e-4)).powf(length)
+}
+
+fn bounced_light(
+    canvas: &ReferenceCanvas,
+    previous: &[Vec4],
+    settings: &RaymarchSettings,
+    sample_uv: Vec2,
+    emission: Vec4,
+) -> Vec4 {
+    if settings.bounce_strength <= 0.0 {
+        return Vec4::ZERO;
+    }
+    let reflected = (canvas.sample(previous, sample_uv) - emission).max(Vec4::ZERO);
+    (settings.bounce_strength * reflected)
+        .truncate()
+        .extend(0.0)
+}
+
+/// Lights `canvas` the way one frame of the raymarch pass does, returning one value per pixel.
+///
+/// The resolution is taken from the canvas, like the plugin keeps `settings.resolution` up to date.
+pub fn raymarch(canvas: &ReferenceCanvas, settings: &RaymarchSettings) -> Vec<Vec4> {
+    let nothing = vec![Vec4::ZERO; canvas.emission.len()];
+    raymarch_with_previous(canvas, settings, &nothing)
+}
+
+/// Lights `canvas` the way the raymarch pass does on the frame after it output `previous`, over the
+/// same canvas. Surfaces reflect the light `previous` shows on them by `settings.bounce_strength`.
+pub fn raymarch_with_previous(
+    canvas: &ReferenceCanvas,
+    settings: &RaymarchSettings,
+    previous: &[Vec4],
+) -> Vec<Vec4> {
+    let distance_field = canvas.distance_field();
+    canvas
+        .pixel_centers()
+        .map(|(_, position)| {
+            let uv = position / canvas.size.as_vec2();
+            let lit = raymarch_pixel(canvas, &distance_field, previous, settings, uv);
+            Vec4::new(lit.x, lit.y, lit.z, 1.0)
+        })
+        .collect()
+}
+
+fn raymarch_pixel(
+    canvas: &ReferenceCanvas,
+    distance_field: &[f32],
+    previous: &[Vec4],
+    settings: &RaymarchSettings,
+    uv: Vec2,
+) -> Vec4 {
+    let resolution = canvas.size.as_vec2();
+    let surface = canvas.sample(&canvas.albedo, uv);
+    let reciprocal_raycount = 1.0 / settings.ray_count as f32;
+    let tau_raycount = TAU * reciprocal_raycount;
+
+    let frame_offset = settings.frame_index as f32 * Vec2::new(0.754_877_7, 0.569_840_3);
+    let noise = rand(uv + Vec2::new(fract(frame_offset.x), fract(frame_offset.y)));
+    let longest_side = resolution.x.max(resolution.y);
+    let mut radiance = Vec4::ZERO;
+
+    for i in 0..settings.ray_count {
+        let angle = tau_raycount * (i as f32 + noise);
+        let direction = Vec2::new(angle.cos(), -angle.sin());
+        let ray_direction = direction / resolution;
+        let mut travelled = 0.0;
+        let mut inside = surface.w > MEDIUM_MAX_ALPHA;
+        let mut throughput = Vec3::ONE;
+
+        for _ in 0..settings.max_steps {
+            let sample_uv = uv + ray_direction * travelled;
+
+            if out_of_bounds(sample_uv) {
+                radiance += (throughput * canvas.sky.radiance(direction)).extend(0.0);
+                break;
+            }
+
+            let material = canvas.sample(&canvas.albedo, sample_uv);
+            let occluder = material.w > MEDIUM_MAX_ALPHA;
+
+            if occluder && !inside {
+                let emission = canvas.sample(&canvas.emission, sample_uv);
+                radiance += throughput.extend(1.0)
+                    * (emission + bounced_light(canvas, previous, settings, sample_uv, emission));
+                break;
+            }
+            inside = inside && occluder;
+
+            let nearest_solid = canvas.sample(distance_field, sample_uv) * longest_side;
+            let step_length = nearest_solid.max(1.0);
+
+            if is_medium(material) {
+                let transmittance = medium_transmittance(material, step_length);
+                let emission = canvas.sample(&canvas.emission, sample_uv).truncate();
+                radiance += (throughput * emission * step_length).extend(0.0);
+                throughput *= transmittance;
+                if throughput.max_element() < MIN_TRANSMITTANCE {
+                    break;
+                }
+            }
+            travelled += step_length;
+        }
+    }
+
+    let irradiance = radiance * reciprocal_raycount;
+
+    if surface.w > 0.1 {
+        let emitted = canvas.sample(&canvas.emission, uv);
+        return emitted + surface * irradiance;
+    }
+
+    irradiance
+}
+
+/// Marches one interval of a cascade ray the way `march_interval` in `cascades.wgsl` does, from
+/// `origin` in pixels along `direction`. The interval is the one `cascade_index` covers, which for
+/// any cascade above 0 never starts inside a surface.
+///
+/// rgb is the radiance that was hit, and alpha how much of the cascade above gets through the
+/// interval, 0.0 once something solid is hit. Only a single frame is traced, so nothing is bounced
+/// back by `bounce_strength`.
+pub fn march_cascade_interval(
+    canvas: &ReferenceCanvas,
+    settings: &CascadeSettings,
+    cascade_index: u32,
+    origin: Vec2,
+    direction: Vec2,
+) -> Vec4 {
+    let distance_field = canvas.distance_field();
+    let resolution = canvas.size.as_vec2();
+    let longest_side = resolution.max_element();
+    let level_scale = (1u32 << cascade_index) as f32;
+    let start = settings.base_interval * (level_scale - 1.0);
+    let length = settings.base_interval * level_scale;
+    let mut travelled = start;
+    let mut radiance = Vec3::ZERO;
+    let mut throughput = Vec3::ONE;
+
+    for _ in 0..settings.max_steps {
+        if travelled > start + length {
+            break;
+        }
+
+        let position = origin + direction * travelled;
+        if position.x < 0.0
+            || position.y < 0.0
+            || position.x >= resolution.x
+            || position.y >= resolution.y
+        {
+            return (radiance + throughput * canvas.sky.radiance(direction)).extend(0.0);
+        }
+
+        let index = position.y as usize * canvas.size.x as usize + position.x as usize;
+        let material = canvas.albedo[index];
+        if material.w > MEDIUM_MAX_ALPHA {
+            return (radiance + throughput * canvas.emission[index].truncate()).extend(0.0);
+        }
+
+        let step_length = (distance_field[index] * longest_side).max(1.0);
+
+        if is_medium(material) {
+            let transmittance = medium_transmittance(material, step_length);
+            let emission = canvas.emission[index].truncate();
+            radiance += throughput * emission * step_length;
+            throughput *= transmittance;
+            if throughput.max_element() < MIN_TRANSMITTANCE {
+                return radiance.extend(0.0);
+            }
+        }
+        travelled += step_length;
+    }
+
+    radiance.extend(throughput.element_sum() / 3.0)
+}
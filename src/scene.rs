@@ -0,0 +1,448 @@
+use bevy::{
+    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    ecs::query::QueryItem,
+    math::Affine2,
+    prelude::*,
+    render::{
+        Extract,
+        render_asset::RenderAssets,
+        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_resource::{
+            binding_types::{storage_buffer_read_only, texture_2d},
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::{CachedTexture, GpuImage},
+    },
+};
+
+use crate::{
+    CanvasImages, GiDomain, GiTextureFormats, PostProcessSettings, SCENE_SHADER_ASSET_PATH,
+    contributors::GiSceneImages,
+};
+
+/// A shape in the local space of its entity's `Transform`, measured in world units.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum RadianceShape {
+    Circle {
+        radius: f32,
+    },
+    Box {
+        half_size: Vec2,
+    },
+    /// A line between two points, with round ends.
+    Segment {
+        start: Vec2,
+        end: Vec2,
+        thickness: f32,
+    },
+    /// A pill along the local x axis.
+    Capsule {
+        half_length: f32,
+        radius: f32,
+    },
+}
+
+/// Gives off light in the shape of [`RadianceShape`]. Emitters are solid, so they block light as well.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+#[require(Transform, Visibility)]
+pub struct RadianceEmitter {
+    pub shape: RadianceShape,
+    pub color: Color,
+    /// Multiplies `color`, anything above 1.0 relies on the HDR output.
+    pub intensity: f32,
+}
+
+impl RadianceEmitter {
+    pub fn new(shape: RadianceShape, color: Color, intensity: f32) -> Self {
+        Self {
+            shape,
+            color,
+            intensity,
+        }
+    }
+}
+
+/// Blocks light in the shape of [`RadianceShape`], and is lit with `albedo` as its diffuse colour.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+#[require(Transform, Visibility)]
+pub struct RadianceOccluder {
+    pub shape: RadianceShape,
+    pub albedo: Color,
+}
+
+impl RadianceOccluder {
+    pub fn new(shape: RadianceShape, albedo: Color) -> Self {
+        Self { shape, albedo }
+    }
+}
+
+const SHAPE_CIRCLE: u32 = 0;
+const SHAPE_BOX: u32 = 1;
+const SHAPE_SEGMENT: u32 = 2;
+
+#[derive(Clone, Copy, Default, ShaderType)]
+struct GpuRadianceShape {
+    /// The rows of the affine transform from canvas uv to the shape's local space.
+    local_from_uv_x: Vec3,
+    local_from_uv_y: Vec3,
+    kind: u32,
+    /// The circle's radius, or half the segment's thickness.
+    radius: f32,
+    /// The half size of a box, or the two ends of a segment.
+    params: Vec4,
+    emission: Vec3,
+    albedo: Vec3,
+}
+
+#[derive(Default, ShaderType)]
+struct GpuRadianceShapes {
+    count: u32,
+    #[size(runtime)]
+    shapes: Vec<GpuRadianceShape>,
+}
+
+/// The emitters and occluders of this frame, in the order they're drawn.
+#[derive(Resource, Default)]
+pub(crate) struct ExtractedRadianceShapes(Vec<GpuRadianceShape>);
+
+/// The GI scene with the canvas and every emitter and occluder drawn over it. This is what the GI
+/// passes see.
+pub(crate) struct SceneGBuffer {
+    pub(crate) emission: CachedTexture,
+    pub(crate) albedo: CachedTexture,
+}
+
+/// Unlike the other GI textures the scene G-buffers outlive the frame, so the temporal passes can
+/// compare against last frame's scene. Every GI camera lights the same scene, so there's only one.
+#[derive(Resource, Default)]
+pub(crate) struct SceneTextures {
+    shapes: StorageBuffer<GpuRadianceShapes>,
+    gbuffers: Option<[SceneGBuffer; 2]>,
+    current: usize,
+}
+
+impl SceneTextures {
+    /// The scene G-buffer written this frame.
+    pub(crate) fn gbuffer(&self) -> Option<&SceneGBuffer> {
+        self.gbuffers
+            .as_ref()
+            .map(|gbuffers| &gbuffers[self.current])
+    }
+
+    /// The scene G-buffer written last frame.
+    pub(crate) fn previous_gbuffer(&self) -> Option<&SceneGBuffer> {
+        self.gbuffers
+            .as_ref()
+            .map(|gbuffers| &gbuffers[1 - self.current])
+    }
+}
+
+/// Flattens a transform onto the xy plane, which is the only part that matters in 2D.
+fn affine2(transform: &GlobalTransform) -> Affine2 {
+    let affine = transform.affine();
+    Affine2::from_mat2_translation(
+        Mat2::from_cols(
+            affine.matrix3.x_axis.truncate(),
+            affine.matrix3.y_axis.truncate(),
+        ),
+        affine.translation.truncate(),
+    )
+}
+
+pub(crate) fn extract_radiance_shapes(
+    mut extracted: ResMut<ExtractedRadianceShapes>,
+    domain: Extract<Option<Res<GiDomain>>>,
+    occluders: Extract<Query<(&RadianceOccluder, &GlobalTransform, &InheritedVisibility)>>,
+    emitters: Extract<Query<(&RadianceEmitter, &GlobalTransform, &InheritedVisibility)>>,
+) {
+    extracted.0.clear();
+    let Some(domain) = &*domain else {
+        return;
+    };
+
+    // The domain is what the canvas covers, so that's what maps the world onto canvas uvs.
+    let size = domain.size;
+    let world_from_uv = Affine2::from_translation(domain.center)
+        * Affine2::from_mat2_translation(
+            Mat2::from_diagonal(Vec2::new(size.x, -size.y)),
+            Vec2::new(-0.5 * size.x, 0.5 * size.y),
+        );
+
+    let mut push = |shape: RadianceShape, transform: &GlobalTransform, emission, albedo| {
+        let local_from_uv = affine2(transform).inverse() * world_from_uv;
+        let (kind, radius, params) = match shape {
+            RadianceShape::Circle { radius } => (SHAPE_CIRCLE, radius, Vec4::ZERO),
+            RadianceShape::Box { half_size } => (SHAPE_BOX, 0.0, half_size.extend(0.0).extend(0.0)),
+            RadianceShape::Segment {
+                start,
+                end,
+                thickness,
+            } => (
+                SHAPE_SEGMENT,
+                thickness * 0.5,
+                Vec4::new(start.x, start.y, end.x, end.y),
+            ),
+            RadianceShape::Capsule {
+                half_length,
+                radius,
+            } => (
+                SHAPE_SEGMENT,
+                radius,
+                Vec4::new(-half_length, 0.0, half_length, 0.0),
+            ),
+        };
+        extracted.0.push(GpuRadianceShape {
+            local_from_uv_x: Vec3::new(
+                local_from_uv.matrix2.x_axis.x,
+                local_from_uv.matrix2.y_axis.x,
+                local_from_uv.translation.x,
+            ),
+            local_from_uv_y: Vec3::new(
+                local_from_uv.matrix2.x_axis.y,
+                local_from_uv.matrix2.y_axis.y,
+                local_from_uv.translation.y,
+            ),
+            kind,
+            radius,
+            params,
+            emission,
+            albedo,
+        });
+    };
+
+    let rgb = |color: Color| {
+        let color = color.to_linear();
+        Vec3::new(color.red, color.green, color.blue)
+    };
+
+    // Emitters go last so a light inside a wall still shows.
+    for (occluder, transform, visibility) in &occluders {
+        if visibility.get() {
+            push(occluder.shape, transform, Vec3::ZERO, rgb(occluder.albedo));
+        }
+    }
+    for (emitter, transform, visibility) in &emitters {
+        if visibility.get() {
+            let color = rgb(emitter.color);
+            push(emitter.shape, transform, color * emitter.intensity, color);
+        }
+    }
+}
+
+pub(crate) fn prepare_scene(
+    canvas_images: Option<Res<CanvasImages>>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    formats: Res<GiTextureFormats>,
+    extracted: Res<ExtractedRadianceShapes>,
+    mut scene: ResMut<SceneTextures>,
+) {
+    let Some(canvas) = canvas_images.and_then(|images| gpu_images.get(&images.target().albedo))
+    else {
+        scene.gbuffers = None;
+        return;
+    };
+
+    let mut shapes = extracted.0.clone();
+    let count = shapes.len() as u32;
+    // A storage binding needs at least one element, even when there's nothing to draw.
+    if shapes.is_empty() {
+        shapes.push(GpuRadianceShape::default());
+    }
+    scene.shapes.set(GpuRadianceShapes { count, shapes });
+    scene.shapes.write_buffer(&render_device, &render_queue);
+
+    scene.current = 1 - scene.current;
+    let size = canvas.texture.size();
+    if scene
+        .gbuffers
+        .as_ref()
+        .is_some_and(|gbuffers| gbuffers[0].albedo.texture.size() == size)
+    {
+        return;
+    }
+
+    // Reallocated with the canvas, which leaves last frame's scene blank so all history is rejected
+    let texture = |label, format| {
+        let texture = render_device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        CachedTexture {
+            default_view: texture.create_view(&TextureViewDescriptor::default()),
+            texture,
+        }
+    };
+    let gbuffer = || SceneGBuffer {
+        emission: texture("scene_emission_texture", formats.emission),
+        albedo: texture("scene_albedo_texture", TextureFormat::Rgba8Unorm),
+    };
+    scene.gbuffers = Some([gbuffer(), gbuffer()]);
+}
+
+/// Draws the canvas over the GI scene, then the emitters and occluders over both, into the scene
+/// G-buffer the GI passes read.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct ScenePassLabel;
+
+#[derive(Default)]
+pub(crate) struct SceneNode;
+
+impl ViewNode for SceneNode {
+    // The camera that paints draws the scene, after the canvas pass. Every other GI camera renders
+    // later in the frame and lights the same scene.
+    type ViewQuery = &'static PostProcessSettings;
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        _settings: QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let scene_pipeline = world.resource::<ScenePipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(scene_pipeline.pipeline_id) else {
+            return Ok(());
+        };
+
+        let scene = world.resource::<SceneTextures>();
+        let (Some(shapes_binding), Some(gbuffer)) = (scene.shapes.binding(), scene.gbuffer())
+        else {
+            return Ok(());
+        };
+
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        // The canvas pass has just written to this side
+        let canvas = world.resource::<CanvasImages>().target();
+        // The GI scene cameras render before this one, so these hold this frame's sprites and meshes
+        let gi_scene = world.resource::<GiSceneImages>();
+        let (Some(gi_albedo), Some(gi_emission)) = (
+            gpu_images.get(&gi_scene.albedo),
+            gpu_images.get(&gi_scene.emission),
+        ) else {
+            return Ok(());
+        };
+
+        let bind_group = render_context.render_device().create_bind_group(
+            "scene_bind_group",
+            &scene_pipeline.layout,
+            &BindGroupEntries::sequential((
+                &gpu_images.get(&canvas.emission).unwrap().texture_view,
+                &gpu_images.get(&canvas.albedo).unwrap().texture_view,
+                shapes_binding,
+                &gi_albedo.texture_view,
+                &gi_emission.texture_view,
+            )),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("scene_pass"),
+            color_attachments: &[
+                color_attachment(&gbuffer.emission),
+                color_attachment(&gbuffer.albedo),
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+/// Draws into `texture` on top of what the earlier passes left there.
+fn color_attachment<'a>(texture: &'a CachedTexture) -> Option<RenderPassColorAttachment<'a>> {
+    Some(RenderPassColorAttachment {
+        view: &texture.default_view,
+        resolve_target: None,
+        ops: Operations {
+            load: LoadOp::Load,
+            store: StoreOp::Store,
+        },
+    })
+}
+
+#[derive(Resource)]
+pub(crate) struct ScenePipeline {
+    layout: BindGroupLayout,
+    pipeline_id: CachedRenderPipelineId,
+}
+
+impl FromWorld for ScenePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let formats = *world.resource::<GiTextureFormats>();
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "scene_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // The canvas G-buffer, copied pixel for pixel
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Every emitter and occluder of this frame
+                    storage_buffer_read_only::<GpuRadianceShapes>(false),
+                    // The GI scene, rendered by the GI scene cameras
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+            ),
+        );
+
+        let shader = world.load_asset(SCENE_SHADER_ASSET_PATH);
+
+        let pipeline_id =
+            world
+                .resource_mut::<PipelineCache>()
+                .queue_render_pipeline(RenderPipelineDescriptor {
+                    label: Some("scene_pipeline".into()),
+                    layout: vec![layout.clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader,
+                        shader_defs: vec![],
+                        entry_point: "fragment".into(),
+                        // Same channels as the canvas
+                        targets: vec![
+                            Some(ColorTargetState {
+                                format: formats.emission,
+                                blend: None,
+                                write_mask: ColorWrites::ALL,
+                            }),
+                            Some(ColorTargetState {
+                                format: TextureFormat::Rgba8Unorm,
+                                blend: None,
+                                write_mask: ColorWrites::ALL,
+                            }),
+                        ],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    push_constant_ranges: vec![],
+                    zero_initialize_workgroup_memory: false,
+                });
+
+        Self {
+            layout,
+            pipeline_id,
+        }
+    }
+}
@@ -0,0 +1,235 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+@group(0) @binding(0) var emission_texture: texture_2d<f32>;
+@group(0) @binding(1) var albedo_texture: texture_2d<f32>;
+
+struct PostProcessSettings {
+    resolution: vec2<f32>,
+    radius_squared: f32,
+    drawing: u32,
+    fro: vec2<f32>,
+    to: vec2<f32>,
+    color: vec3<f32>,
+    emission: f32,
+    tool: u32,
+    hardness: f32,
+    opacity: f32,
+    spacing: f32,
+    stroke_length: f32,
+    continues_stroke: u32,
+    from_pressure: f32,
+    to_pressure: f32,
+    shape_start: vec2<f32>,
+    commit_shape: u32,
+    shape_outline: u32,
+    polygon_sides: u32,
+    density: f32,
+    scroll: vec2<i32>,
+}
+
+// Matches `CanvasTool`. Fills are done on the CPU, so the pass leaves the canvas alone for them.
+const TOOL_BRUSH: u32 = 0u;
+const TOOL_ERASER: u32 = 1u;
+const TOOL_CLEAR: u32 = 2u;
+const TOOL_RECTANGLE: u32 = 4u;
+const TOOL_ELLIPSE: u32 = 5u;
+const TOOL_POLYGON: u32 = 6u;
+const TOOL_LINE: u32 = 7u;
+
+const PI: f32 = 3.14159265;
+
+// Albedo alphas above this are solid, and the ones at or below it are translucent paint
+const MEDIUM_MAX_ALPHA: f32 = 0.5;
+
+@group(0) @binding(2) var<uniform> settings: PostProcessSettings;
+
+struct StrokePoint {
+    position: vec2<f32>,
+    pressure: f32,
+}
+
+struct StrokePath {
+    count: u32,
+    points: array<StrokePoint>,
+}
+
+// The points the stroke passed through between `fro` and `to`
+@group(0) @binding(3) var<storage, read> stroke_path: StrokePath;
+
+struct CanvasOutput {
+    @location(0) emission: vec4<f32>,
+    // rgb is the diffuse colour, alpha the occluder mask
+    @location(1) albedo: vec4<f32>,
+}
+
+// The points of this frame's stroke in order, `fro` first and `to` last.
+fn stroke_point(index: u32) -> StrokePoint {
+    if (index == 0u) {
+        return StrokePoint(settings.fro, settings.from_pressure);
+    }
+    if (index > stroke_path.count) {
+        return StrokePoint(settings.to, settings.to_pressure);
+    }
+    return stroke_path.points[index - 1u];
+}
+
+// How much of the brush covers `coord`, from 0.0 to 1.0. `pixel_size` is the size of a canvas pixel
+// in the space of `coord`, the edge is blended over it to keep strokes from aliasing.
+fn brush_coverage(coord: vec2<f32>, pixel_size: f32) -> f32 {
+    var coverage = 0.0;
+    var start = stroke_point(0u);
+    var start_length = settings.stroke_length;
+    for (var i = 1u; i < stroke_path.count + 2u; i++) {
+        let end = stroke_point(i);
+        // Where segments overlap the strongest one wins, so the joins don't build up paint
+        let continues = settings.continues_stroke != 0u || i > 1u;
+        coverage = max(coverage, segment_coverage(coord, pixel_size, start, end, start_length, continues));
+        start_length += distance(start.position, end.position);
+        start = end;
+    }
+    return coverage;
+}
+
+// How much of the segment of the stroke from `start` to `end` covers `coord`. `start_length` is how
+// far along the stroke `start` is, and `continues` is true when a segment before it painted `start`.
+fn segment_coverage(
+    coord: vec2<f32>,
+    pixel_size: f32,
+    start: StrokePoint,
+    end: StrokePoint,
+    start_length: f32,
+    continues: bool,
+) -> f32 {
+    let line = end.position - start.position;
+    let line_length = length(line);
+    let direction = select(vec2<f32>(1.0, 0.0), line / line_length, line_length > 0.0);
+    let along = dot(coord - start.position, direction);
+    let base_radius = sqrt(settings.radius_squared);
+
+    // Distance along the segment of the point of the stroke nearest to `coord`
+    var nearest = 0.0;
+    if (settings.spacing > 0.0) {
+        // Dabs sit at whole steps along the stroke. The segment before painted the one at `start`.
+        let dab_step = max(settings.spacing * base_radius, pixel_size);
+        var first = 0.0;
+        if (continues) {
+            first = (floor(start_length / dab_step) + 1.0) * dab_step - start_length;
+        }
+        if (first > line_length) {
+            return 0.0;
+        }
+        let last = floor((line_length - first) / dab_step);
+        nearest = first + clamp(round((along - first) / dab_step), 0.0, last) * dab_step;
+    } else {
+        // The round end behind `start` was painted by the segment before
+        if (continues && along < 0.0) {
+            return 0.0;
+        }
+        nearest = clamp(along, 0.0, line_length);
+    }
+
+    let t = select(0.0, nearest / line_length, line_length > 0.0);
+    let radius = base_radius * mix(start.pressure, end.pressure, t);
+    let from_stroke = length(coord - (start.position + direction * nearest));
+    // Full strength out to `hardness` of the radius, then fading to nothing just past the edge
+    let inner = min(radius * settings.hardness, radius - 0.5 * pixel_size);
+    return 1.0 - smoothstep(inner, radius + 0.5 * pixel_size, from_stroke);
+}
+
+// Signed distance from `coord` to the edge of the shape dragged from `shape_start` to `to`,
+// negative inside it.
+fn shape_distance(coord: vec2<f32>) -> f32 {
+    let start = settings.shape_start;
+    let end = settings.to;
+    let radius = sqrt(settings.radius_squared);
+    if (settings.tool == TOOL_LINE) {
+        let line = end - start;
+        let t = clamp(dot(coord - start, line) / max(dot(line, line), 1e-6), 0.0, 1.0);
+        return length(coord - (start + line * t)) - radius;
+    }
+
+    var distance = 0.0;
+    if (settings.tool == TOOL_RECTANGLE) {
+        let half_size = abs(end - start) * 0.5;
+        let q = abs(coord - (start + end) * 0.5) - half_size;
+        distance = length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0);
+    } else if (settings.tool == TOOL_ELLIPSE) {
+        // Not exact away from the edge, but close enough to blend it
+        let radii = max(abs(end - start) * 0.5, vec2<f32>(1e-3));
+        let p = coord - (start + end) * 0.5;
+        distance = (length(p / radii) - 1.0) * min(radii.x, radii.y);
+    } else if (settings.tool == TOOL_POLYGON) {
+        let corner = end - start;
+        let circumradius = length(corner);
+        let sides = f32(max(settings.polygon_sides, 3u));
+        let sector = 2.0 * PI / sides;
+        let p = coord - start;
+        // Fold `p` into one sector, turned so its edge faces the x axis with corners either side
+        let angle = atan2(p.y, p.x) - atan2(corner.y, corner.x);
+        let folded = angle - floor(angle / sector) * sector - sector * 0.5;
+        let q = length(p) * vec2<f32>(cos(folded), sin(folded));
+        let apothem = circumradius * cos(sector * 0.5);
+        let half_edge = circumradius * sin(sector * 0.5);
+        let nearest = vec2<f32>(apothem, clamp(q.y, -half_edge, half_edge));
+        distance = length(q - nearest) * sign(q.x - apothem);
+    }
+
+    if (settings.shape_outline != 0u) {
+        return abs(distance) - radius;
+    }
+    return distance;
+}
+
+// Matches `PostProcessSettings::paint_alpha`. Translucent paint never builds up past
+// `MEDIUM_MAX_ALPHA`, however often it's painted over.
+fn paint_alpha() -> f32 {
+    if (settings.density >= 1.0) {
+        return 1.0;
+    }
+    return max(settings.density, 0.0) * MEDIUM_MAX_ALPHA;
+}
+
+// The paint the brush leaves where it fully covers a pixel, premultiplied like the rest of the canvas
+fn paint(color: vec3<f32>) -> vec4<f32> {
+    let alpha = paint_alpha();
+    return vec4<f32>(color * alpha, alpha);
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> CanvasOutput {
+    var current: CanvasOutput;
+    // The previous canvas is copied pixel for pixel rather than by uv, so when the window is resized
+    // the painting keeps its size and whatever no longer fits is cropped
+    // When the canvas moves with the camera, the paint is read from where it was in the world
+    let pixel = vec2<i32>(in.position.xy) + settings.scroll;
+    if (all(pixel >= vec2<i32>(0)) && all(vec2<u32>(pixel) < textureDimensions(emission_texture))) {
+        current.emission = textureLoad(emission_texture, pixel, 0);
+        current.albedo = textureLoad(albedo_texture, pixel, 0);
+    }
+    let coord = in.uv * settings.resolution;
+    let pixel_size = max(
+        settings.resolution.x / f32(textureDimensions(emission_texture).x),
+        settings.resolution.y / f32(textureDimensions(emission_texture).y),
+    );
+    if (settings.commit_shape != 0u) {
+        let distance = shape_distance(coord);
+        let coverage = (1.0 - smoothstep(-0.5 * pixel_size, 0.5 * pixel_size, distance)) * settings.opacity;
+        current.emission = mix(current.emission, paint(settings.color.rgb * settings.emission), coverage);
+        current.albedo = mix(current.albedo, paint(settings.color.rgb), coverage);
+    } else if (settings.drawing != 0u) {
+        // Shape tools paint nothing while they're being dragged out
+        let coverage = brush_coverage(coord, pixel_size) * settings.opacity;
+        // The emission fades with the coverage, so past the edge where the paint turns translucent
+        // it still gives off light in proportion to how much of it there is
+        if (settings.tool == TOOL_BRUSH) {
+            current.emission = mix(current.emission, paint(settings.color.rgb * settings.emission), coverage);
+            current.albedo = mix(current.albedo, paint(settings.color.rgb), coverage);
+        } else if (settings.tool == TOOL_ERASER) {
+            current.emission = mix(current.emission, vec4<f32>(0.0), coverage);
+            current.albedo = mix(current.albedo, vec4<f32>(0.0), coverage);
+        } else if (settings.tool == TOOL_CLEAR) {
+            current.emission = vec4<f32>(0.0);
+            current.albedo = vec4<f32>(0.0);
+        }
+    }
+    return current;
+}
@@ -0,0 +1,427 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+@group(0) @binding(0) var emission_texture: texture_2d<f32>;
+// rgb is the diffuse colour, alpha the occluder mask
+@group(0) @binding(1) var albedo_texture: texture_2d<f32>;
+
+// The cascade above the one being rendered. When resolving this is cascade 0 instead.
+@group(0) @binding(2) var cascade_texture: texture_2d<f32>;
+
+struct SkyLight {
+    zenith: vec3<f32>,
+    sun_angle: f32,
+    ground: vec3<f32>,
+    sun_angular_radius: f32,
+    sun_color: vec3<f32>,
+    sun_intensity: f32,
+}
+
+struct CascadeLevelUniform {
+    resolution: vec2<f32>,
+    cascade_index: u32,
+    cascade_count: u32,
+    base_interval: f32,
+    max_steps: u32,
+    merge: u32,
+    bounce_strength: f32,
+    scroll: vec2<i32>,
+    sky: SkyLight,
+}
+
+@group(0) @binding(3) var<uniform> settings: CascadeLevelUniform;
+
+// Distance to the nearest solid or translucent pixel, divided by the longest side of the canvas
+@group(0) @binding(4) var distance_texture: texture_2d<f32>;
+
+// Last frame's output and the scene it was lit against
+@group(0) @binding(5) var history_texture: texture_2d<f32>;
+@group(0) @binding(6) var previous_emission_texture: texture_2d<f32>;
+@group(0) @binding(7) var previous_albedo_texture: texture_2d<f32>;
+
+#ifdef COMPUTE
+// The compute passes write straight into the cascade, or the GI output when resolving
+#ifdef OUTPUT_RGBA32F
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba32float, write>;
+#else ifdef OUTPUT_RGBA8
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba8unorm, write>;
+#else
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba16float, write>;
+#endif
+#endif
+
+const PI: f32 = 3.14159265;
+const TAU: f32 = 2.0 * PI;
+
+// Albedo alphas above this are solid, and the ones at or below it are translucent paint
+const MEDIUM_MAX_ALPHA: f32 = 0.5;
+// Rays stop once this little of the light beyond them would get through
+const MIN_TRANSMITTANCE: f32 = 0.01;
+
+fn is_medium(material: vec4<f32>) -> bool {
+    return material.a > 0.0 && material.a <= MEDIUM_MAX_ALPHA;
+}
+
+// How much of each channel gets through `length` pixels of translucent paint. The paint is
+// premultiplied, so its alpha gives both its colour and how dense it is.
+fn medium_transmittance(material: vec4<f32>, length: f32) -> vec3<f32> {
+    let per_pixel = mix(vec3<f32>(1.0), material.rgb / material.a, material.a / MEDIUM_MAX_ALPHA);
+    return pow(max(per_pixel, vec3<f32>(1e-4)), vec3<f32>(length));
+}
+
+// Matches `CascadeMerge`
+const MERGE_NEAREST: u32 = 0u;
+const MERGE_BILINEAR: u32 = 1u;
+const MERGE_BILINEAR_FIX: u32 = 2u;
+const MERGE_PRE_AVERAGED: u32 = 3u;
+
+// Cascade 0 places a probe every 2 pixels, and every level above doubles the spacing.
+fn probe_spacing(cascade_index: u32) -> u32 {
+    return 2u << cascade_index;
+}
+
+// Cascade 0 casts 4 rays from each probe, and every level above doubles the angular resolution.
+fn ray_count(cascade_index: u32) -> u32 {
+    return 4u << cascade_index;
+}
+
+// Each probe stores its rays in a block of texels, 2x2 in cascade 0. Doubling the rays alternately
+// doubles the width and the height, so the blocks stay close to square. Matches `probe_block`.
+fn probe_block(cascade_index: u32) -> vec2<u32> {
+    return vec2<u32>(1u << ((cascade_index + 3u) / 2u), 1u << ((cascade_index + 2u) / 2u));
+}
+
+// Intervals double every level, so a level starts where the sum of all the levels below it ends.
+fn interval_start(cascade_index: u32) -> f32 {
+    return settings.base_interval * (f32(1u << cascade_index) - 1.0);
+}
+
+fn interval_length(cascade_index: u32) -> f32 {
+    return settings.base_interval * f32(1u << cascade_index);
+}
+
+fn out_of_bounds(position: vec2<f32>) -> bool {
+    return position.x < 0.0 || position.x >= settings.resolution.x || position.y < 0.0 || position.y >= settings.resolution.y;
+}
+
+// The radiance arriving from beyond the canvas along `direction`, with y pointing down the screen.
+// Matches `SkyLight::radiance`.
+fn sky_radiance(direction: vec2<f32>) -> vec3<f32> {
+    let sky = settings.sky;
+    let unit = normalize(direction);
+    let gradient = mix(sky.ground, sky.zenith, -unit.y * 0.5 + 0.5);
+    let sun_direction = vec2<f32>(cos(sky.sun_angle), -sin(sky.sun_angle));
+    let from_sun = acos(clamp(dot(unit, sun_direction), -1.0, 1.0));
+    return gradient + select(vec3<f32>(0.0), sky.sun_color * sky.sun_intensity, from_sun < sky.sun_angular_radius);
+}
+
+// The light the surface at `pixel` reflects, taken from last frame's output where the surface was lit
+// and the scene there hasn't changed since. Matches `bounced_light` in `raymarching.wgsl`.
+fn bounced_light(pixel: vec2<i32>, emission: vec3<f32>) -> vec3<f32> {
+    if (settings.bounce_strength <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    // Where this pixel's part of the world was last frame, which moves when the canvas follows the camera
+    let previous = pixel + settings.scroll;
+    if (any(previous < vec2<i32>(0))) {
+        return vec3<f32>(0.0);
+    }
+    if (any(vec2<u32>(previous) >= textureDimensions(history_texture)) || any(vec2<u32>(previous) >= textureDimensions(previous_albedo_texture))) {
+        return vec3<f32>(0.0);
+    }
+    let albedo_change = abs(textureLoad(albedo_texture, pixel, 0) - textureLoad(previous_albedo_texture, previous, 0));
+    let emission_change = abs(textureLoad(emission_texture, pixel, 0) - textureLoad(previous_emission_texture, previous, 0));
+    if (any(albedo_change > vec4<f32>(0.01)) || any(emission_change > vec4<f32>(0.01))) {
+        return vec3<f32>(0.0);
+    }
+    // Last frame's output is what the surface emits plus what it reflected
+    let lit = textureLoad(history_texture, previous, 0).rgb;
+    return settings.bounce_strength * max(lit - emission, vec3<f32>(0.0));
+}
+
+// Marches a single interval of a ray, in pixels.
+// rgb is the radiance that was hit, and alpha is how much of the cascade above gets through the interval,
+// 0.0 once it hits something solid.
+// Rays starting `inside` a surface ignore it until they leave, so surfaces can gather the light arriving at them.
+// An interval that runs out of steps before its end is left unresolved, it passes on the cascade above
+// as if nothing lay in the rest of it.
+fn march_interval(origin: vec2<f32>, direction: vec2<f32>, start: f32, length: f32, starts_inside: bool) -> vec4<f32> {
+    let longest_side = max(settings.resolution.x, settings.resolution.y);
+    var travelled = start;
+    var inside = starts_inside;
+    var radiance = vec3<f32>(0.0);
+    var throughput = vec3<f32>(1.0);
+
+    for (var step = 0u; step < settings.max_steps; step += 1u) {
+        if (travelled > start + length) {
+            break;
+        }
+
+        let position = origin + direction * travelled;
+        // Rays that leave the canvas see the sky, and nothing the cascade above could add
+        if (out_of_bounds(position)) {
+            return vec4<f32>(radiance + throughput * sky_radiance(direction), 0.0);
+        }
+
+        let pixel = vec2<i32>(position);
+        let material = textureLoad(albedo_texture, pixel, 0);
+        let occluder = material.a > MEDIUM_MAX_ALPHA;
+        if (occluder && !inside) {
+            let emission = textureLoad(emission_texture, pixel, 0).rgb;
+            return vec4<f32>(radiance + throughput * (emission + bounced_light(pixel, emission)), 0.0);
+        }
+        inside = inside && occluder;
+
+        // Never step past the nearest surface, however long the interval, or thin walls get skipped
+        let nearest_solid = textureLoad(distance_texture, pixel, 0).r * longest_side;
+        let step_length = max(nearest_solid, 1.0);
+
+        // Translucent paint dims and tints the light behind it. Its emission is premultiplied like the
+        // rest of it, so faint paint and the soft edges of a brush glow by as much as they cover
+        if (is_medium(material)) {
+            let transmittance = medium_transmittance(material, step_length);
+            let emission = textureLoad(emission_texture, pixel, 0).rgb;
+            radiance += throughput * emission * step_length;
+            throughput *= transmittance;
+            if (max(throughput.r, max(throughput.g, throughput.b)) < MIN_TRANSMITTANCE) {
+                return vec4<f32>(radiance, 0.0);
+            }
+        }
+        travelled += step_length;
+    }
+
+    // The cascade above is merged in by a single value, so the light from beyond this interval is
+    // dimmed by the paint in it but not tinted
+    return vec4<f32>(radiance, (throughput.r + throughput.g + throughput.b) / 3.0);
+}
+
+fn load_ray(probe: vec2<i32>, ray_index: u32, block: vec2<u32>) -> vec4<f32> {
+    let probe_count = vec2<i32>(textureDimensions(cascade_texture) / block);
+    let clamped_probe = clamp(probe, vec2<i32>(0), probe_count - 1);
+    let ray_offset = vec2<i32>(i32(ray_index % block.x), i32(ray_index / block.x));
+    return textureLoad(cascade_texture, clamped_probe * vec2<i32>(block) + ray_offset, 0);
+}
+
+// Averages `count` consecutive rays of a single probe of `cascade_texture`.
+fn probe_rays(probe: vec2<i32>, block: vec2<u32>, first_ray: u32, count: u32) -> vec4<f32> {
+    var radiance = vec4<f32>(0.0);
+    for (var i = 0u; i < count; i += 1u) {
+        radiance += load_ray(probe, first_ray + i, block);
+    }
+    return radiance / f32(count);
+}
+
+// Averages `count` consecutive rays, bilinearly interpolated between the four probes of `cascade_texture` surrounding `position`.
+// The probes are `spacing` pixels apart, and each stores its rays in a `block` sized rectangle of texels.
+fn sample_probes(position: vec2<f32>, spacing: u32, block: vec2<u32>, first_ray: u32, count: u32) -> vec4<f32> {
+    let grid = position / f32(spacing) - 0.5;
+    let base = vec2<i32>(floor(grid));
+    let weight = fract(grid);
+
+    let top = mix(
+        probe_rays(base, block, first_ray, count),
+        probe_rays(base + vec2<i32>(1, 0), block, first_ray, count),
+        weight.x,
+    );
+    let bottom = mix(
+        probe_rays(base + vec2<i32>(0, 1), block, first_ray, count),
+        probe_rays(base + vec2<i32>(1, 1), block, first_ray, count),
+        weight.x,
+    );
+    return mix(top, bottom, weight.y);
+}
+
+fn is_top_cascade() -> bool {
+    return settings.cascade_index + 1u >= settings.cascade_count;
+}
+
+fn merge(radiance: vec4<f32>, upper: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(radiance.rgb + radiance.a * upper.rgb, radiance.a * upper.a);
+}
+
+// Marches this cascade's interval along `direction` and merges in `count` rays of the cascade above,
+// the ones covering the same angle further out.
+fn trace_ray(origin: vec2<f32>, direction: vec2<f32>, starts_inside: bool, first_upper_ray: u32, count: u32) -> vec4<f32> {
+    let radiance = march_interval(
+        origin,
+        direction,
+        interval_start(settings.cascade_index),
+        interval_length(settings.cascade_index),
+        starts_inside,
+    );
+
+    if (radiance.a == 0.0) {
+        return radiance;
+    }
+    // Nothing lies beyond the top cascade, so its unobstructed rays go on to the sky
+    if (is_top_cascade()) {
+        return merge(radiance, vec4<f32>(sky_radiance(direction), 1.0));
+    }
+
+    let upper_spacing = probe_spacing(settings.cascade_index + 1u);
+    let upper_block = probe_block(settings.cascade_index + 1u);
+    if (settings.merge == MERGE_NEAREST) {
+        let nearest = vec2<i32>(floor(origin / f32(upper_spacing)));
+        return merge(radiance, probe_rays(nearest, upper_block, first_upper_ray, count));
+    }
+    if (settings.merge == MERGE_PRE_AVERAGED) {
+        // `cascade_texture` holds the cascade above already averaged, the `count` rays this ray merges
+        // in one texel, stored in blocks the size of this cascade's
+        let block = probe_block(settings.cascade_index);
+        return merge(radiance, sample_probes(origin, upper_spacing, block, first_upper_ray / count, 1u));
+    }
+    return merge(radiance, sample_probes(origin, upper_spacing, upper_block, first_upper_ray, count));
+}
+
+// Traces from the start of this ray's interval to the start of the interval of each of the four
+// probes above, so a wall between this probe and one of them blocks that probe's light.
+fn trace_bilinear_fix(origin: vec2<f32>, direction: vec2<f32>, starts_inside: bool, ray_index: u32) -> vec4<f32> {
+    if (is_top_cascade()) {
+        return trace_ray(origin, direction, starts_inside, 0u, 0u);
+    }
+
+    let start = interval_start(settings.cascade_index);
+    let end = start + interval_length(settings.cascade_index);
+    let interval_begin = origin + direction * start;
+    let upper_spacing = probe_spacing(settings.cascade_index + 1u);
+    let upper_block = probe_block(settings.cascade_index + 1u);
+    let probe_count = vec2<i32>(textureDimensions(cascade_texture) / upper_block);
+    let grid = origin / f32(upper_spacing) - 0.5;
+    let base = vec2<i32>(floor(grid));
+    let weight = fract(grid);
+
+    var radiance = vec4<f32>(0.0);
+    for (var corner = 0u; corner < 4u; corner += 1u) {
+        let offset = vec2<i32>(i32(corner % 2u), i32(corner / 2u));
+        let upper_probe = clamp(base + offset, vec2<i32>(0), probe_count - 1);
+        let upper_begin = (vec2<f32>(upper_probe) + 0.5) * f32(upper_spacing) + direction * end;
+        let towards = upper_begin - interval_begin;
+        let traced = march_interval(interval_begin, normalize(towards), 0.0, length(towards), starts_inside);
+        let upper = probe_rays(upper_probe, upper_block, ray_index * 2u, 2u);
+        let bilinear = select(1.0 - weight, weight, offset == vec2<i32>(1));
+        radiance += bilinear.x * bilinear.y * merge(traced, upper);
+    }
+    return radiance;
+}
+
+// Traces and merges the ray stored in one texel of the cascade being rendered.
+fn trace_cascade(texel: vec2<u32>) -> vec4<f32> {
+    let block = probe_block(settings.cascade_index);
+    let probe = texel / block;
+    let ray_in_block = texel % block;
+    let ray_index = ray_in_block.y * block.x + ray_in_block.x;
+
+    let origin = (vec2<f32>(probe) + 0.5) * f32(probe_spacing(settings.cascade_index));
+
+    // Only cascade 0 starts at the probe, the intervals above it start far enough out that skipping
+    // a surface there would let light leak through walls
+    let starts_inside = settings.cascade_index == 0u && !out_of_bounds(origin)
+        && textureLoad(albedo_texture, vec2<i32>(origin), 0).a > MEDIUM_MAX_ALPHA;
+
+    let angle = TAU * (f32(ray_index) + 0.5) / f32(ray_count(settings.cascade_index));
+    let direction = vec2<f32>(cos(angle), -sin(angle));
+    if (settings.merge == MERGE_BILINEAR_FIX) {
+        return trace_bilinear_fix(origin, direction, starts_inside, ray_index);
+    }
+    // The two rays of the level above cover the same angle as this ray, further out.
+    return trace_ray(origin, direction, starts_inside, ray_index * 2u, 2u);
+}
+
+// Averages the two rays of a probe of `cascade_texture` that a single ray of the cascade below merges
+// into one texel, so merging them takes one read instead of two. The averaged probes are stored in
+// blocks half the size, the size of the cascade below's.
+fn average_rays(texel: vec2<u32>) -> vec4<f32> {
+    let block = probe_block(settings.cascade_index);
+    let averaged_block = probe_block(settings.cascade_index - 1u);
+    let probe = texel / averaged_block;
+    let ray_in_block = texel % averaged_block;
+    let lower_ray = ray_in_block.y * averaged_block.x + ray_in_block.x;
+    return probe_rays(vec2<i32>(probe), block, lower_ray * 2u, 2u);
+}
+
+// Lights a pixel with the irradiance gathered by cascade 0.
+fn shade_pixel(pixel: vec2<i32>, irradiance: vec4<f32>) -> vec4<f32> {
+    // Surfaces reflect the light arriving at them by their albedo, on top of whatever they emit
+    let surface = textureLoad(albedo_texture, pixel, 0);
+    if (surface.a > 0.1) {
+        let emitted = textureLoad(emission_texture, pixel, 0);
+        return vec4<f32>(emitted.rgb + surface.rgb * irradiance.rgb, 1.0);
+    }
+
+    return vec4<f32>(irradiance.rgb, 1.0);
+}
+
+@fragment
+fn cascade(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return trace_cascade(vec2<u32>(in.position.xy));
+}
+
+@fragment
+fn average(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return average_rays(vec2<u32>(in.position.xy));
+}
+
+@fragment
+fn resolve(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let irradiance = sample_probes(in.position.xy, probe_spacing(0u), probe_block(0u), 0u, ray_count(0u));
+    return shade_pixel(vec2<i32>(in.position.xy), irradiance);
+}
+
+#ifdef COMPUTE
+const WORKGROUP_SIZE: u32 = 8u;
+// An 8x8 tile of pixels interpolates between the cascade 0 probes under it, plus one on each side
+const PROBE_TILE: u32 = WORKGROUP_SIZE / 2u + 2u;
+
+// The average of every ray of the probes around this workgroup's tile. Bilinear interpolation and
+// averaging commute, so each probe is averaged once here instead of once per pixel
+var<workgroup> probe_cache: array<vec4<f32>, PROBE_TILE * PROBE_TILE>;
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn cascade_compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= textureDimensions(output_texture))) {
+        return;
+    }
+    textureStore(output_texture, id.xy, trace_cascade(id.xy));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn average_compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= textureDimensions(output_texture))) {
+        return;
+    }
+    textureStore(output_texture, id.xy, average_rays(id.xy));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn resolve_compute(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let spacing = probe_spacing(0u);
+    let rays = ray_count(0u);
+    // The probe at the top left of the tile, matching the `floor(position / spacing - 0.5)` of `sample_probes`
+    let first_probe = vec2<i32>(workgroup.xy * (WORKGROUP_SIZE / spacing)) - 1;
+
+    if (local_index < PROBE_TILE * PROBE_TILE) {
+        let probe = first_probe + vec2<i32>(i32(local_index % PROBE_TILE), i32(local_index / PROBE_TILE));
+        var radiance = vec4<f32>(0.0);
+        for (var ray_index = 0u; ray_index < rays; ray_index += 1u) {
+            radiance += load_ray(probe, ray_index, probe_block(0u));
+        }
+        probe_cache[local_index] = radiance / f32(rays);
+    }
+    workgroupBarrier();
+
+    if (any(id.xy >= textureDimensions(output_texture))) {
+        return;
+    }
+
+    let position = vec2<f32>(id.xy) + 0.5;
+    let grid = position / f32(spacing) - 0.5;
+    let local = vec2<u32>(vec2<i32>(floor(grid)) - first_probe);
+    let weight = fract(grid);
+    let cached = local.y * PROBE_TILE + local.x;
+    let top = mix(probe_cache[cached], probe_cache[cached + 1u], weight.x);
+    let bottom = mix(probe_cache[cached + PROBE_TILE], probe_cache[cached + PROBE_TILE + 1u], weight.x);
+    textureStore(output_texture, id.xy, shade_pixel(vec2<i32>(id.xy), mix(top, bottom, weight.y)));
+}
+#endif
@@ -0,0 +1,36 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+@group(0) @binding(0) var emission_texture: texture_2d<f32>;
+@group(0) @binding(1) var albedo_texture: texture_2d<f32>;
+
+struct CompositeOutput {
+    @location(0) emission: vec4<f32>,
+    @location(1) albedo: vec4<f32>,
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> CompositeOutput {
+    // Layers are the size of the canvas, except on the frame one of them is resized
+    var emission = vec4<f32>(0.0);
+    var albedo = vec4<f32>(0.0);
+    let pixel = vec2<u32>(in.position.xy);
+    if (all(pixel < textureDimensions(emission_texture))) {
+        emission = textureLoad(emission_texture, pixel, 0);
+        albedo = textureLoad(albedo_texture, pixel, 0);
+    }
+    // Paint is premultiplied by how much of the pixel it covers, which the alpha of the albedo holds
+    let coverage = albedo.a;
+#ifdef ROLE_OCCLUDER
+    emission = vec4<f32>(0.0, 0.0, 0.0, coverage);
+#endif
+
+    var out: CompositeOutput;
+#ifdef BLEND_MULTIPLY
+    // Tints by the paint's colour where it covers the pixel, and by 1.0 where it doesn't
+    out.emission = vec4<f32>(emission.rgb + (1.0 - coverage), coverage);
+    out.albedo = vec4<f32>(albedo.rgb + (1.0 - coverage), coverage);
+#else
+    out.emission = vec4<f32>(emission.rgb, coverage);
+    out.albedo = albedo;
+#endif
+    return out;
+}
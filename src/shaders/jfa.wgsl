@@ -0,0 +1,73 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+
+struct JfaUniform {
+    resolution: vec2<f32>,
+    step_size: u32,
+}
+
+@group(0) @binding(1) var<uniform> settings: JfaUniform;
+
+// Marks pixels that don't have a seed yet. Any negative coordinate works.
+const NO_SEED: vec2<f32> = vec2<f32>(-1.0);
+
+fn has_seed(seed: vec2<f32>) -> bool {
+    return seed.x >= 0.0;
+}
+
+@fragment
+fn plant_seeds(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let current = textureLoad(source_texture, vec2<i32>(in.position.xy), 0);
+    // Translucent paint is seeded as well as solid, so rays slow down to cross it pixel by pixel
+    // instead of skipping over it
+    if (current.a > 0.0) {
+        return vec4<f32>(in.position.xy, 0.0, 0.0);
+    }
+    return vec4<f32>(NO_SEED, 0.0, 0.0);
+}
+
+@fragment
+fn jump_flood(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let max_pixel = vec2<i32>(settings.resolution) - 1;
+    let step_size = i32(settings.step_size);
+
+    var nearest_seed = NO_SEED;
+    var nearest_distance = 3.4e38;
+
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let neighbour = pixel + vec2<i32>(x, y) * step_size;
+            if (any(neighbour < vec2<i32>(0)) || any(neighbour > max_pixel)) {
+                continue;
+            }
+
+            let candidate = textureLoad(source_texture, neighbour, 0).xy;
+            if (!has_seed(candidate)) {
+                continue;
+            }
+
+            let offset = candidate - in.position.xy;
+            let distance_squared = dot(offset, offset);
+            if (distance_squared < nearest_distance) {
+                nearest_distance = distance_squared;
+                nearest_seed = candidate;
+            }
+        }
+    }
+
+    return vec4<f32>(nearest_seed, 0.0, 0.0);
+}
+
+@fragment
+fn write_distance(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let nearest_seed = textureLoad(source_texture, vec2<i32>(in.position.xy), 0).xy;
+    let longest_side = max(settings.resolution.x, settings.resolution.y);
+
+    // Nothing solid on the canvas, so every ray can skip straight across it
+    if (!has_seed(nearest_seed)) {
+        return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    }
+
+    return vec4<f32>(length(nearest_seed - in.position.xy) / longest_side, 0.0, 0.0, 1.0);
+}
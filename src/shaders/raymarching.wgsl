@@ -0,0 +1,299 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+@group(0) @binding(0) var emission_texture: texture_2d<f32>;
+// rgb is the diffuse colour, alpha the occluder mask
+@group(0) @binding(1) var albedo_texture: texture_2d<f32>;
+
+struct SkyLight {
+    zenith: vec3<f32>,
+    sun_angle: f32,
+    ground: vec3<f32>,
+    sun_angular_radius: f32,
+    sun_color: vec3<f32>,
+    sun_intensity: f32,
+}
+
+struct RaymarchSettings {
+    resolution: vec2<f32>,
+    ray_count: u32,
+    max_steps: u32,
+    frame_index: u32,
+    history_blend: f32,
+    bounce_strength: f32,
+    scroll: vec2<i32>,
+}
+
+@group(0) @binding(2) var<uniform> settings: RaymarchSettings;
+
+// Distance to the nearest solid or translucent pixel, divided by the longest side of the screen
+@group(0) @binding(3) var distance_texture: texture_2d<f32>;
+
+// Last frame's output and the scene it was traced against
+@group(0) @binding(4) var history_texture: texture_2d<f32>;
+@group(0) @binding(5) var previous_emission_texture: texture_2d<f32>;
+@group(0) @binding(6) var previous_albedo_texture: texture_2d<f32>;
+
+// The camera's `SkyLight`
+@group(0) @binding(7) var<uniform> sky: SkyLight;
+
+#ifdef COMPUTE
+// The GI output, written directly instead of through a render attachment
+#ifdef OUTPUT_RGBA32F
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba32float, write>;
+#else ifdef OUTPUT_RGBA8
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba8unorm, write>;
+#else
+@group(1) @binding(0) var output_texture: texture_storage_2d<rgba16float, write>;
+#endif
+
+const WORKGROUP_SIZE: u32 = 8u;
+// Rays take their shortest steps close to the pixel they start from, so each workgroup loads the
+// scene under its tile, and this far around it, once for all of its rays. A 16x16 tile of the three
+// channels fits well within the 16KiB of workgroup memory every device offers.
+const TILE_APRON: u32 = 4u;
+const TILE_SIZE: u32 = WORKGROUP_SIZE + 2u * TILE_APRON;
+
+var<workgroup> emission_cache: array<vec4<f32>, TILE_SIZE * TILE_SIZE>;
+var<workgroup> albedo_cache: array<vec4<f32>, TILE_SIZE * TILE_SIZE>;
+var<workgroup> distance_cache: array<f32, TILE_SIZE * TILE_SIZE>;
+// The pixel at the top left of the cached tile
+var<private> cache_origin: vec2<i32>;
+
+// Where `pixel` is in the caches, or -1 when it lies outside this workgroup's tile
+fn cache_index(pixel: vec2<i32>) -> i32 {
+    let local = pixel - cache_origin;
+    if (any(local < vec2<i32>(0)) || any(local >= vec2<i32>(i32(TILE_SIZE)))) {
+        return -1;
+    }
+    return local.y * i32(TILE_SIZE) + local.x;
+}
+#endif
+
+// The scene is read through these, from the workgroup's cache where the compute pass has it
+fn load_emission(pixel: vec2<i32>) -> vec4<f32> {
+#ifdef COMPUTE
+    let index = cache_index(pixel);
+    if (index >= 0) {
+        return emission_cache[index];
+    }
+#endif
+    return textureLoad(emission_texture, pixel, 0);
+}
+
+fn load_albedo(pixel: vec2<i32>) -> vec4<f32> {
+#ifdef COMPUTE
+    let index = cache_index(pixel);
+    if (index >= 0) {
+        return albedo_cache[index];
+    }
+#endif
+    return textureLoad(albedo_texture, pixel, 0);
+}
+
+fn load_distance(pixel: vec2<i32>) -> f32 {
+#ifdef COMPUTE
+    let index = cache_index(pixel);
+    if (index >= 0) {
+        return distance_cache[index];
+    }
+#endif
+    return textureLoad(distance_texture, pixel, 0).r;
+}
+
+// The pixel a nearest, clamped sample at `uv` reads
+fn uv_to_pixel(uv: vec2<f32>) -> vec2<i32> {
+    return clamp(vec2<i32>(floor(uv * settings.resolution)), vec2<i32>(0), vec2<i32>(settings.resolution) - 1);
+}
+
+const PI: f32 = 3.14159265;
+const TAU: f32 = 2.0 * PI;
+
+// Albedo alphas above this are solid, and the ones at or below it are translucent paint
+const MEDIUM_MAX_ALPHA: f32 = 0.5;
+// Rays stop once this little of the light beyond them would get through
+const MIN_TRANSMITTANCE: f32 = 0.01;
+
+fn is_medium(material: vec4<f32>) -> bool {
+    return material.a > 0.0 && material.a <= MEDIUM_MAX_ALPHA;
+}
+
+// How much of each channel gets through `length` pixels of translucent paint. The paint is
+// premultiplied, so its alpha gives both its colour and how dense it is.
+fn medium_transmittance(material: vec4<f32>, length: f32) -> vec3<f32> {
+    let per_pixel = mix(vec3<f32>(1.0), material.rgb / material.a, material.a / MEDIUM_MAX_ALPHA);
+    return pow(max(per_pixel, vec3<f32>(1e-4)), vec3<f32>(length));
+}
+
+fn rand(in: vec2<f32>) -> f32 {
+    let magic_vec = vec2<f32>(12.9898f, 78.233f);
+    return fract(sin(dot(in, magic_vec) * 43758.5453));
+}
+
+fn out_of_bounds(uv: vec2<f32>) -> bool {
+    return uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0;
+}
+
+// The radiance arriving from beyond the canvas along `direction`, with y pointing down the screen.
+// Matches `SkyLight::radiance`.
+fn sky_radiance(direction: vec2<f32>) -> vec3<f32> {
+    let unit = normalize(direction);
+    let gradient = mix(sky.ground, sky.zenith, -unit.y * 0.5 + 0.5);
+    let sun_direction = vec2<f32>(cos(sky.sun_angle), -sin(sky.sun_angle));
+    let from_sun = acos(clamp(dot(unit, sun_direction), -1.0, 1.0));
+    return gradient + select(vec3<f32>(0.0), sky.sun_color * sky.sun_intensity, from_sun < sky.sun_angular_radius);
+}
+
+// The light a surface hit by a ray reflects, taken from last frame's output where the surface was
+// lit. Feeding it back every frame adds one more bounce each time, until it converges.
+fn bounced_light(sample_uv: vec2<f32>, emission: vec4<f32>) -> vec4<f32> {
+    if (settings.bounce_strength <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let pixel = vec2<u32>(sample_uv * settings.resolution);
+    if (!scene_unchanged(pixel)) {
+        return vec4<f32>(0.0);
+    }
+    // Last frame's output is what the surface emits plus what it reflected
+    let previous = textureLoad(history_texture, previous_pixel(pixel), 0).rgb;
+    return vec4<f32>(settings.bounce_strength * max(previous - emission.rgb, vec3<f32>(0.0)), 0.0);
+}
+
+// `reference.rs` mirrors this on the CPU for the golden image tests, keep the two in step
+fn raymarch(uv: vec2<f32>) -> vec4<f32> {
+    let surface = load_albedo(uv_to_pixel(uv));
+    let reciprocal_raycount = 1.0 / f32(settings.ray_count);
+    let tau_raycount = TAU * reciprocal_raycount;
+
+    // The noise moves every frame so the accumulated history averages over many ray angles
+    let frame_offset = fract(f32(settings.frame_index) * vec2<f32>(0.754877669, 0.569840296));
+    let noise = rand(uv + frame_offset);
+    let longest_side = max(settings.resolution.x, settings.resolution.y);
+    var radiance = vec4<f32>(0.0);
+
+    for (var i = 0u; i < settings.ray_count; i += 1u) {
+        let angle = tau_raycount * (f32(i) + noise);
+        let direction = vec2<f32>(cos(angle), -sin(angle));
+        let ray_direction = direction / settings.resolution;
+        var travelled = 0.0;
+        // Rays cast from inside a surface have to leave it before they can hit anything,
+        // otherwise every surface would only ever see itself
+        var inside = surface.a > MEDIUM_MAX_ALPHA;
+        // How much of the light from further along the ray makes it through the paint crossed so far
+        var throughput = vec3<f32>(1.0);
+
+        for (var step = 0u; step < settings.max_steps; step += 1u) {
+            let sample_uv = uv + (ray_direction * travelled);
+
+            // Rays that leave the canvas see the sky
+            if (out_of_bounds(sample_uv)) {
+                radiance += vec4<f32>(throughput * sky_radiance(direction), 0.0);
+                break;
+            }
+
+            let sample_pixel = uv_to_pixel(sample_uv);
+            let material = load_albedo(sample_pixel);
+            let occluder = material.a > MEDIUM_MAX_ALPHA;
+
+            if (occluder && !inside) {
+                let emission = load_emission(sample_pixel);
+                radiance += vec4<f32>(throughput, 1.0) * (emission + bounced_light(sample_uv, emission));
+                break;
+            }
+            inside = inside && occluder;
+
+            // Nothing solid or translucent is closer than this, so we can skip straight past it
+            let nearest_solid = load_distance(sample_pixel) * longest_side;
+            let step_length = max(nearest_solid, 1.0);
+
+            // Translucent paint dims and tints the light behind it. Its emission is premultiplied like the
+            // rest of it, so faint paint and the soft edges of a brush glow by as much as they cover
+            if (is_medium(material)) {
+                let transmittance = medium_transmittance(material, step_length);
+                let emission = load_emission(sample_pixel);
+                radiance += vec4<f32>(throughput * emission.rgb * step_length, 0.0);
+                throughput *= transmittance;
+                if (max(throughput.r, max(throughput.g, throughput.b)) < MIN_TRANSMITTANCE) {
+                    break;
+                }
+            }
+            travelled += step_length;
+        }
+    }
+
+    let irradiance = radiance * reciprocal_raycount;
+
+    // Surfaces reflect the light arriving at them by their albedo, on top of whatever they emit
+    if (surface.a > 0.1) {
+        let emitted = load_emission(uv_to_pixel(uv));
+        return emitted + surface * irradiance;
+    }
+
+    return irradiance;
+}
+
+
+// Where this pixel's part of the world was last frame, which moves when the canvas follows the camera
+fn previous_pixel(pixel: vec2<u32>) -> vec2<i32> {
+    return vec2<i32>(pixel) + settings.scroll;
+}
+
+// Whether last frame's output at this pixel was traced against the same scene
+fn scene_unchanged(pixel: vec2<u32>) -> bool {
+    let previous = previous_pixel(pixel);
+    if (any(previous < vec2<i32>(0))) {
+        return false;
+    }
+    if (any(vec2<u32>(previous) >= textureDimensions(history_texture)) || any(vec2<u32>(previous) >= textureDimensions(previous_albedo_texture))) {
+        return false;
+    }
+    let albedo_change = abs(textureLoad(albedo_texture, pixel, 0) - textureLoad(previous_albedo_texture, previous, 0));
+    let emission_change = abs(textureLoad(emission_texture, pixel, 0) - textureLoad(previous_emission_texture, previous, 0));
+    return all(albedo_change <= vec4<f32>(0.01)) && all(emission_change <= vec4<f32>(0.01));
+}
+
+// How much of last frame's output to keep, none where the pixel's scene changed or wasn't there before
+fn history_weight(pixel: vec2<u32>) -> f32 {
+    return select(0.0, settings.history_blend, scene_unchanged(pixel));
+}
+
+fn shade_pixel(pixel: vec2<u32>, uv: vec2<f32>) -> vec4<f32> {
+    let history_size = vec2<i32>(textureDimensions(history_texture));
+    let history = textureLoad(history_texture, clamp(previous_pixel(pixel), vec2<i32>(0), history_size - 1), 0);
+    let final_color = mix(raymarch(uv), history, history_weight(pixel));
+    return vec4<f32>(final_color.xyz, 1.0);
+}
+
+// Workgroup memory can't be reached from a fragment shader, so the compute build leaves this out
+#ifndef COMPUTE
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return shade_pixel(vec2<u32>(in.position.xy), in.uv);
+}
+#endif
+
+#ifdef COMPUTE
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+fn raymarch_compute(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    cache_origin = vec2<i32>(workgroup.xy * WORKGROUP_SIZE) - i32(TILE_APRON);
+    let canvas_size = vec2<i32>(textureDimensions(albedo_texture));
+    for (var i = local_index; i < TILE_SIZE * TILE_SIZE; i += WORKGROUP_SIZE * WORKGROUP_SIZE) {
+        let offset = vec2<i32>(i32(i % TILE_SIZE), i32(i / TILE_SIZE));
+        // Parts of the tile past the edge are never read, as every lookup is clamped to the canvas first
+        let pixel = clamp(cache_origin + offset, vec2<i32>(0), canvas_size - 1);
+        emission_cache[i] = textureLoad(emission_texture, pixel, 0);
+        albedo_cache[i] = textureLoad(albedo_texture, pixel, 0);
+        distance_cache[i] = textureLoad(distance_texture, pixel, 0).r;
+    }
+    workgroupBarrier();
+
+    let size = textureDimensions(output_texture);
+    if (any(id.xy >= size)) {
+        return;
+    }
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    textureStore(output_texture, id.xy, shade_pixel(id.xy, uv));
+}
+#endif
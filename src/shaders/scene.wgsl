@@ -0,0 +1,90 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+const SHAPE_CIRCLE: u32 = 0u;
+const SHAPE_BOX: u32 = 1u;
+const SHAPE_SEGMENT: u32 = 2u;
+
+struct RadianceShape {
+    // Rows of the affine transform from canvas uv to the shape's local space
+    local_from_uv_x: vec3<f32>,
+    local_from_uv_y: vec3<f32>,
+    kind: u32,
+    radius: f32,
+    params: vec4<f32>,
+    emission: vec3<f32>,
+    albedo: vec3<f32>,
+}
+
+struct RadianceShapes {
+    count: u32,
+    shapes: array<RadianceShape>,
+}
+
+@group(0) @binding(0) var emission_texture: texture_2d<f32>;
+@group(0) @binding(1) var albedo_texture: texture_2d<f32>;
+@group(0) @binding(2) var<storage, read> scene: RadianceShapes;
+// Sprites and meshes, rendered with premultiplied colour over a transparent clear
+@group(0) @binding(3) var gi_albedo_texture: texture_2d<f32>;
+@group(0) @binding(4) var gi_emission_texture: texture_2d<f32>;
+
+struct SceneOutput {
+    @location(0) emission: vec4<f32>,
+    @location(1) albedo: vec4<f32>,
+}
+
+fn sdf_box(p: vec2<f32>, half_size: vec2<f32>) -> f32 {
+    let d = abs(p) - half_size;
+    return length(max(d, vec2<f32>(0.0))) + min(max(d.x, d.y), 0.0);
+}
+
+fn sdf_segment(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let start = p - a;
+    let line = b - a;
+    let t = clamp(dot(start, line) / max(dot(line, line), 1e-8), 0.0, 1.0);
+    return length(start - line * t);
+}
+
+// Signed distance in the shape's local units, only the sign matters since the transform may be scaled
+fn sdf_shape(shape: RadianceShape, p: vec2<f32>) -> f32 {
+    switch shape.kind {
+        case SHAPE_CIRCLE: {
+            return length(p) - shape.radius;
+        }
+        case SHAPE_BOX: {
+            return sdf_box(p, shape.params.xy);
+        }
+        default: {
+            return sdf_segment(p, shape.params.xy, shape.params.zw) - shape.radius;
+        }
+    }
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> SceneOutput {
+    let pixel = vec2<u32>(in.position.xy);
+    var out: SceneOutput;
+
+    // Sprites and meshes are the base of the scene, their alpha is the occluder mask
+    let gi_albedo = textureLoad(gi_albedo_texture, pixel, 0);
+    if (gi_albedo.a > 0.5) {
+        out.albedo = vec4<f32>(gi_albedo.rgb / gi_albedo.a, 1.0);
+        out.emission = vec4<f32>(textureLoad(gi_emission_texture, pixel, 0).rgb, 1.0);
+    }
+
+    // The premultiplied canvas paint goes over them, so translucent paint only tints a solid sprite
+    let canvas_albedo = textureLoad(albedo_texture, pixel, 0);
+    let canvas_emission = textureLoad(emission_texture, pixel, 0);
+    out.albedo = canvas_albedo + (1.0 - canvas_albedo.a) * out.albedo;
+    out.emission = canvas_emission + (1.0 - canvas_albedo.a) * out.emission;
+
+    let uv = vec3<f32>(in.uv, 1.0);
+    for (var i = 0u; i < scene.count; i++) {
+        let shape = scene.shapes[i];
+        let p = vec2<f32>(dot(shape.local_from_uv_x, uv), dot(shape.local_from_uv_y, uv));
+        if (sdf_shape(shape, p) <= 0.0) {
+            out.emission = vec4<f32>(shape.emission, 1.0);
+            out.albedo = vec4<f32>(shape.albedo, 1.0);
+        }
+    }
+    return out;
+}
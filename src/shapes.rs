@@ -0,0 +1,86 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::{CanvasTool, GiDomain, PostProcessSettings};
+
+/// Keeps track of where a shape tool's drag started, and tells the canvas pass to paint the shape
+/// on the frame the drag ends.
+pub(crate) fn track_shapes(
+    mut was_drawing: Local<bool>,
+    mut brushes: Query<&mut PostProcessSettings>,
+) {
+    let Ok(mut brush) = brushes.single_mut() else {
+        return;
+    };
+    let drawing = brush.drawing != 0;
+    // The first frame of a stroke has `to` on the cursor, wherever the last stroke left `from`
+    if drawing && !*was_drawing {
+        brush.shape_start = brush.to;
+    }
+    let commit = u32::from(!drawing && *was_drawing && brush.tool().is_shape());
+    if brush.commit_shape != commit {
+        brush.commit_shape = commit;
+    }
+    *was_drawing = drawing;
+}
+
+/// Outlines the shape being dragged out over the canvas, until it's painted into the canvas.
+pub(crate) fn preview_shapes(
+    mut gizmos: Gizmos,
+    brushes: Query<&PostProcessSettings>,
+    domain: Option<Res<GiDomain>>,
+) {
+    let (Ok(brush), Some(domain)) = (brushes.single(), domain) else {
+        return;
+    };
+    if brush.drawing == 0 || !brush.tool().is_shape() || brush.resolution.min_element() <= 0.0 {
+        return;
+    }
+
+    // The brush's space has its origin in the top left corner of the canvas, with y going down
+    let to_world = |coord: Vec2| {
+        domain.center + (coord / brush.resolution - 0.5) * domain.size * Vec2::new(1.0, -1.0)
+    };
+    let color = Color::linear_rgb(brush.color.x, brush.color.y, brush.color.z);
+    gizmos.linestrip_2d(
+        shape_outline(
+            brush.tool(),
+            brush.shape_start,
+            brush.to,
+            brush.polygon_sides,
+        )
+        .into_iter()
+        .map(to_world),
+        color,
+    );
+}
+
+/// Points along the edge of the shape dragged from `start` to `end`, in the space of the brush.
+fn shape_outline(tool: CanvasTool, start: Vec2, end: Vec2, polygon_sides: u32) -> Vec<Vec2> {
+    let center = (start + end) * 0.5;
+    let ring = |around: Vec2, sides: u32, corner: Vec2| -> Vec<Vec2> {
+        (0..=sides)
+            .map(|side| Vec2::from_angle(TAU * side as f32 / sides as f32).rotate(corner) + around)
+            .collect()
+    };
+    match tool {
+        CanvasTool::Rectangle => vec![
+            start,
+            Vec2::new(end.x, start.y),
+            end,
+            Vec2::new(start.x, end.y),
+            start,
+        ],
+        CanvasTool::Ellipse => {
+            let radii = (end - start).abs() * 0.5;
+            ring(Vec2::ZERO, 64, Vec2::X)
+                .into_iter()
+                .map(|point| point * radii + center)
+                .collect()
+        }
+        CanvasTool::Polygon => ring(start, polygon_sides.max(3), end - start),
+        CanvasTool::Line => vec![start, end],
+        _ => Vec::new(),
+    }
+}
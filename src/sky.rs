@@ -0,0 +1,72 @@
+use bevy::{
+    prelude::*,
+    render::{extract_component::ExtractComponent, render_resource::ShaderType},
+};
+
+/// Radiance arriving from beyond the edge of the canvas, picked up by every ray that leaves it.
+///
+/// Both GI modes light the canvas of the camera it's on with it. Cameras with
+/// [`RaymarchSettings`](crate::RaymarchSettings) or [`CascadeSettings`](crate::CascadeSettings) get
+/// the default one if they don't have their own.
+///
+/// The sky fades from `ground` for rays heading straight down the screen to `zenith` for rays heading
+/// straight up, and the sun adds `sun_color` within `sun_angular_radius` of its direction. The
+/// default is black everywhere, which leaves the edge of the canvas as a void.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, ShaderType, ExtractComponent)]
+pub struct SkyLight {
+    pub zenith: Vec3,
+    /// The direction the sunlight comes from, in radians counterclockwise from the right of the
+    /// screen, so `FRAC_PI_2` is straight up.
+    pub sun_angle: f32,
+    pub ground: Vec3,
+    /// Half the angle the sun covers, in radians. Wider suns give softer shadows.
+    pub sun_angular_radius: f32,
+    pub sun_color: Vec3,
+    /// Multiplies `sun_color`, anything above 1.0 relies on the HDR output.
+    pub sun_intensity: f32,
+}
+
+impl SkyLight {
+    /// The same radiance from every direction.
+    pub fn ambient(color: Vec3) -> Self {
+        Self::gradient(color, color)
+    }
+
+    /// Radiance that fades from `ground` below to `zenith` above.
+    pub fn gradient(zenith: Vec3, ground: Vec3) -> Self {
+        Self {
+            zenith,
+            ground,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a sun shining from `angle`, see [`SkyLight::sun_angle`].
+    pub fn with_sun(
+        mut self,
+        angle: f32,
+        angular_radius: f32,
+        color: Vec3,
+        intensity: f32,
+    ) -> Self {
+        self.sun_angle = angle;
+        self.sun_angular_radius = angular_radius;
+        self.sun_color = color;
+        self.sun_intensity = intensity;
+        self
+    }
+
+    /// The radiance arriving along `direction`, given with y pointing down the screen like the
+    /// canvas. This is `sky_radiance` in the GI shaders.
+    pub fn radiance(&self, direction: Vec2) -> Vec3 {
+        let direction = direction.normalize_or_zero();
+        let sky = self.ground.lerp(self.zenith, -direction.y * 0.5 + 0.5);
+        let sun_direction = Vec2::new(self.sun_angle.cos(), -self.sun_angle.sin());
+        let from_sun = direction.dot(sun_direction).clamp(-1.0, 1.0).acos();
+        if from_sun < self.sun_angular_radius {
+            sky + self.sun_color * self.sun_intensity
+        } else {
+            sky
+        }
+    }
+}
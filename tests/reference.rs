@@ -0,0 +1,290 @@
+//! Checks the CPU reference raymarcher against the golden images in `tests/golden`.
+//!
+//! After an intended change to the raymarcher, run `BLESS=1 cargo test --test reference` to rewrite
+//! the golden images, and look over the new ones before committing them.
+
+use std::{f32::consts::FRAC_PI_2, path::Path};
+
+use bevy::math::{IVec2, UVec2, Vec2, Vec3, Vec4};
+use bevy_radiance_cascades::{
+    CascadeSettings, RaymarchSettings, SkyLight,
+    reference::{ReferenceCanvas, march_cascade_interval, raymarch, raymarch_with_previous},
+};
+use image::{ImageBuffer, Rgba};
+
+const SIZE: UVec2 = UVec2::splat(48);
+/// Largest difference allowed in a channel, enough to absorb float differences between platforms.
+const TOLERANCE: f32 = 2.0 / 255.0;
+/// Share of pixels allowed past `TOLERANCE`, as a tiny change in the noise can tip a ray over an edge.
+const OUTLIER_FRACTION: f32 = 0.01;
+
+// Golden images are 16 bit PNGs of the linear output, so the scenes are kept within 0.0..=1.0
+const LIGHT: Vec4 = Vec4::new(1.0, 0.8, 0.5, 1.0);
+const BLACK_SOLID: Vec4 = Vec4::new(0.0, 0.0, 0.0, 1.0);
+const GREY_SOLID: Vec4 = Vec4::new(0.8, 0.8, 0.8, 1.0);
+
+fn settings(frame_index: u32) -> RaymarchSettings {
+    RaymarchSettings {
+        resolution: SIZE.as_vec2(),
+        ray_count: 16,
+        max_steps: 64,
+        frame_index,
+        history_blend: 0.0,
+        bounce_strength: 0.0,
+        scroll: IVec2::ZERO,
+    }
+}
+
+fn lone_light() -> ReferenceCanvas {
+    let mut canvas = ReferenceCanvas::new(SIZE);
+    canvas.fill_circle(Vec2::splat(24.0), 5.0, LIGHT, BLACK_SOLID);
+    canvas
+}
+
+fn shadow() -> ReferenceCanvas {
+    let mut canvas = ReferenceCanvas::new(SIZE);
+    canvas.fill_circle(Vec2::new(10.0, 24.0), 4.0, LIGHT, BLACK_SOLID);
+    canvas.fill_rect(
+        Vec2::new(22.0, 12.0),
+        Vec2::new(26.0, 36.0),
+        Vec4::ZERO,
+        GREY_SOLID,
+    );
+    canvas
+}
+
+fn check_golden(name: &str, canvas: &ReferenceCanvas, settings: &RaymarchSettings) {
+    let output: Vec<Vec4> = raymarch(canvas, settings)
+        .into_iter()
+        .map(|value| value.clamp(Vec4::ZERO, Vec4::ONE))
+        .collect();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+
+    if std::env::var_os("BLESS").is_some() {
+        let image = ImageBuffer::<Rgba<u16>, _>::from_fn(SIZE.x, SIZE.y, |x, y| {
+            let value = output[(y * SIZE.x + x) as usize];
+            Rgba(
+                value
+                    .to_array()
+                    .map(|channel| (channel * 65535.0).round() as u16),
+            )
+        });
+        image.save(&path).unwrap();
+        return;
+    }
+
+    let golden = image::open(&path)
+        .unwrap_or_else(|error| {
+            panic!(
+                "couldn't open {}, run with BLESS=1 to create it: {error}",
+                path.display()
+            )
+        })
+        .to_rgba32f();
+    assert_eq!(golden.dimensions(), (SIZE.x, SIZE.y));
+
+    let outliers = golden
+        .enumerate_pixels()
+        .filter(|&(x, y, expected)| {
+            let actual = output[(y * SIZE.x + x) as usize].to_array();
+            actual
+                .iter()
+                .zip(expected.0)
+                .any(|(actual, expected)| (actual - expected).abs() > TOLERANCE)
+        })
+        .count();
+    let allowed = (OUTLIER_FRACTION * (SIZE.x * SIZE.y) as f32) as usize;
+    assert!(
+        outliers <= allowed,
+        "{name}: {outliers} pixels differ from {}, at most {allowed} may",
+        path.display()
+    );
+}
+
+#[test]
+fn lone_light_matches_golden() {
+    check_golden("lone_light", &lone_light(), &settings(0));
+}
+
+#[test]
+fn shadow_matches_golden() {
+    check_golden("shadow", &shadow(), &settings(0));
+}
+
+#[test]
+fn shadow_with_frame_noise_matches_golden() {
+    // A later frame moves the noise, so the frame offset is covered too
+    check_golden("shadow_frame_7", &shadow(), &settings(7));
+}
+
+#[test]
+fn empty_canvas_is_black() {
+    let output = raymarch(&ReferenceCanvas::new(SIZE), &settings(0));
+    assert!(
+        output
+            .iter()
+            .all(|&value| value == Vec4::new(0.0, 0.0, 0.0, 1.0))
+    );
+}
+
+#[test]
+fn wall_blocks_the_light() {
+    let output = raymarch(&shadow(), &settings(0));
+    let brightness = |x: u32, y: u32| output[(y * SIZE.x + x) as usize].x;
+    // Level with the light, in front of the wall and behind it
+    assert!(brightness(16, 24) > 4.0 * brightness(34, 24));
+}
+
+#[test]
+fn faint_paint_over_a_wall_keeps_it_solid() {
+    // The edge of a soft white brush stroked across the wall sprite
+    let mut canvas = shadow();
+    canvas.paint_rect(
+        Vec2::new(16.0, 20.0),
+        Vec2::new(32.0, 28.0),
+        Vec4::ZERO,
+        Vec4::splat(0.2),
+    );
+    let output = raymarch(&canvas, &settings(0));
+    let brightness = |x: u32, y: u32| output[(y * SIZE.x + x) as usize].x;
+    assert!(brightness(16, 24) > 4.0 * brightness(34, 24));
+}
+
+#[test]
+fn sky_lights_an_open_canvas() {
+    let mut canvas = ReferenceCanvas::new(SIZE);
+    canvas.sky = SkyLight::ambient(Vec3::ONE);
+    let output = raymarch(&canvas, &settings(0));
+    assert!(
+        output
+            .iter()
+            .all(|value| (value.truncate() - Vec3::ONE).abs().max_element() < 1e-5)
+    );
+}
+
+#[test]
+fn sun_casts_a_shadow() {
+    let mut canvas = ReferenceCanvas::new(SIZE);
+    canvas.fill_rect(
+        Vec2::new(16.0, 10.0),
+        Vec2::new(32.0, 14.0),
+        Vec4::ZERO,
+        GREY_SOLID,
+    );
+    // Straight overhead, and narrow enough that the wall hides all of it from below its middle
+    canvas.sky = SkyLight::default().with_sun(FRAC_PI_2, 0.3, Vec3::ONE, 1.0);
+    let settings = RaymarchSettings {
+        ray_count: 64,
+        ..settings(0)
+    };
+    let output = raymarch(&canvas, &settings);
+    let brightness = |x: u32, y: u32| output[(y * SIZE.x + x) as usize].x;
+    assert!(brightness(4, 30) > 0.05);
+    assert_eq!(brightness(24, 30), 0.0);
+}
+
+#[test]
+fn light_bounces_around_a_wall() {
+    let mut canvas = ReferenceCanvas::new(SIZE);
+    canvas.fill_circle(Vec2::new(8.0, 8.0), 3.0, Vec4::ONE, Vec4::ONE);
+    canvas.fill_rect(
+        Vec2::new(20.0, 0.0),
+        Vec2::new(24.0, 28.0),
+        Vec4::ZERO,
+        GREY_SOLID,
+    );
+    // Lit by the light through the gap under the wall, and seen from the far side of it
+    canvas.fill_rect(
+        Vec2::new(0.0, 44.0),
+        Vec2::new(48.0, 48.0),
+        Vec4::ZERO,
+        GREY_SOLID,
+    );
+    let settings = RaymarchSettings {
+        ray_count: 64,
+        bounce_strength: 0.5,
+        ..settings(0)
+    };
+    let first = raymarch(&canvas, &settings);
+    let second = raymarch_with_previous(&canvas, &settings, &first);
+    let brightness = |output: &[Vec4], x: u32, y: u32| output[(y * SIZE.x + x) as usize].x;
+    assert_eq!(brightness(&first, 36, 8), 0.0);
+    assert!(brightness(&second, 36, 8) > 0.0);
+}
+
+#[test]
+fn glass_tints_the_light_through_it() {
+    let mut canvas = ReferenceCanvas::new(SIZE);
+    canvas.fill_circle(Vec2::new(8.0, 24.0), 4.0, Vec4::ONE, Vec4::ONE);
+    // Red glass at half density, premultiplied like painted glass
+    let alpha = 0.25;
+    canvas.fill_rect(
+        Vec2::new(20.0, 0.0),
+        Vec2::new(24.0, 48.0),
+        Vec4::ZERO,
+        Vec4::new(1.0, 0.2, 0.2, 1.0) * alpha,
+    );
+    let output = raymarch(&canvas, &settings(0));
+    let lit = output[(24 * SIZE.x + 40) as usize];
+    assert!(lit.x > 0.0);
+    assert!(lit.x > 2.0 * lit.y);
+}
+
+#[test]
+fn thin_wall_blocks_an_upper_cascade_interval() {
+    // Cascade 5 covers 496 to 1008 pixels out, 4 pixels per step if they were spread evenly
+    let settings = CascadeSettings {
+        base_interval: 16.0,
+        max_steps: 128,
+        ..Default::default()
+    };
+    let mut canvas = ReferenceCanvas::new(UVec2::new(1024, 16));
+    // A floor 3 pixels from the ray keeps the distance field from ever allowing a long step
+    canvas.fill_rect(
+        Vec2::new(0.0, 4.0),
+        Vec2::new(1024.0, 5.0),
+        Vec4::ZERO,
+        GREY_SOLID,
+    );
+    canvas.fill_rect(
+        Vec2::new(726.0, 0.0),
+        Vec2::new(746.0, 16.0),
+        LIGHT,
+        BLACK_SOLID,
+    );
+    let origin = Vec2::new(0.5, 7.5);
+
+    let open = march_cascade_interval(&canvas, &settings, 5, origin, Vec2::X);
+    assert_eq!(open, LIGHT.truncate().extend(0.0));
+
+    // Two pixels thick, so an evenly spread step would cross it without landing in it
+    canvas.fill_rect(
+        Vec2::new(626.0, 0.0),
+        Vec2::new(628.0, 16.0),
+        Vec4::ZERO,
+        GREY_SOLID,
+    );
+    let blocked = march_cascade_interval(&canvas, &settings, 5, origin, Vec2::X);
+    assert_eq!(blocked, Vec4::ZERO);
+}
+
+#[test]
+fn soft_brush_edge_gives_off_graded_light() {
+    // The falloff of a soft white brush, where it leaves too little paint to be solid. White paint
+    // tints nothing, so all of the light comes from its emission.
+    let brightness = |coverage: f32| {
+        let mut canvas = ReferenceCanvas::new(SIZE);
+        canvas.fill_rect(
+            Vec2::new(20.0, 20.0),
+            Vec2::new(28.0, 28.0),
+            LIGHT * coverage,
+            Vec4::splat(coverage),
+        );
+        raymarch(&canvas, &settings(0))[(24 * SIZE.x + 40) as usize].x
+    };
+    let [faint, middle, strong] = [0.1, 0.25, 0.4].map(brightness);
+    assert!(faint > 0.0);
+    assert!(faint < middle && middle < strong);
+}